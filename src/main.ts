@@ -1,5 +1,7 @@
 import './style.css';
-import init, { StateManager } from 'wasm';
+import init, {
+  BiomeThresholds, ElevationParams, ErosionParams, Light, NoData, StateManager, heightmap_png, hillshade_png,
+} from 'wasm';
 
 const canvas = document.getElementById("canvas") as HTMLCanvasElement;
 const context = canvas.getContext("2d") as CanvasRenderingContext2D;
@@ -19,13 +21,127 @@ function render(state: StateManager, draw = true) {
   context.putImageData(imageData, 0, 0);
 }
 
+// downloads a PNG file
+function download(png: Uint8Array, name: string) {
+  const blob = new Blob([png], { type: "image/png" });
+  const link = document.createElement("a");
+  link.href = URL.createObjectURL(blob);
+  link.download = name;
+  link.click();
+  URL.revokeObjectURL(link.href);
+}
+
+// downloads the current view as a PNG file
+function saveScreenshot(state: StateManager) {
+  download(state.get_png(), "world.png");
+}
+
+const WATER_LEVEL = -2;
+const WORLD_SIZE = 150;
+const PERIODS = new Uint32Array([20, 8]);
+const AMPLITUDES = new Float32Array([9, 7]);
+// heights exported noise can reach either side of 0, which is also the range dropped heightmaps are read with
+const NOISE_REACH = AMPLITUDES.reduce((sum, a) => sum + Math.abs(a), 0);
+// seed of the current world, if it was generated from noise with a fixed size
+let noiseSeed: bigint | undefined;
+
+// downloads the current world's ground, after erosion and any edits, as a 16 bit heightmap and a hillshade
+// the heightmap uses the range dropped heightmaps are read with, so dropping it back rebuilds the world
+// with octaves, also downloads the noise a generated world came from, and each octave of it on its own
+function saveWorld(state: StateManager, octaves: boolean) {
+  try {
+    download(state.get_heightmap_png(2 * NOISE_REACH, -NOISE_REACH), "world-heightmap.png");
+    download(state.get_hillshade_png(WATER_LEVEL), "world-hillshade.png");
+  } catch (e) {
+    console.log(`Can't save the world's heightmap: ${e}`);
+    return;
+  }
+  if (!octaves) {
+    return;
+  }
+  if (noiseSeed === undefined) {
+    console.log("Only generated worlds have noise to save");
+    return;
+  }
+  const layers: (number | undefined)[] = [undefined];
+  PERIODS.forEach((_, i) => layers.push(i));
+  for (const octave of layers) {
+    const name = octave === undefined ? "noise" : `octave${octave + 1}`;
+    download(heightmap_png(WORLD_SIZE, WORLD_SIZE, PERIODS, AMPLITUDES, noiseSeed, octave), `${name}.png`);
+    download(
+      hillshade_png(WORLD_SIZE, WORLD_SIZE, PERIODS, AMPLITUDES, noiseSeed, WATER_LEVEL, octave),
+      `${name}-hillshade.png`,
+    );
+  }
+}
+
+// biome cutoffs used when generating worlds; adjust fields here to change how biomes are assigned
+function biomeThresholds() {
+  const biomes = BiomeThresholds.new();
+  biomes.snow_height = 8;
+  biomes.beach_height = -3;
+  return biomes;
+}
+
+// droplet erosion applied to finite worlds before they're turned into blocks
+function erosionParams(seed: bigint) {
+  const erosion = ErosionParams.new();
+  erosion.iterations = 40000;
+  erosion.seed = seed;
+  return erosion;
+}
+
 function randomState() {
   const offsetX = Math.floor(canvas.width * 0.5);
   const offsetY = 50;
+  const seed = BigInt(Math.floor(Math.random() * 2 ** 32));
+  console.log(`Generating world with seed ${seed}`);
+  noiseSeed = seed;
   const state = StateManager.new(
-    150, 150, 
-    new Uint32Array([20, 8]), new Float32Array([9, 7]),
-    canvas.height, canvas.width, 12,
+    WORLD_SIZE, WORLD_SIZE, PERIODS, AMPLITUDES,
+    canvas.height, canvas.width, 12, seed, WATER_LEVEL, biomeThresholds(), erosionParams(seed),
+  );
+  state.shift(-offsetX, -offsetY);
+  return state;
+}
+
+function infiniteState() {
+  const offsetX = Math.floor(canvas.width * 0.5);
+  const offsetY = Math.floor(canvas.height * 0.5);
+  const seed = BigInt(Math.floor(Math.random() * 2 ** 32));
+  console.log(`Generating infinite world with seed ${seed}`);
+  noiseSeed = undefined;
+  const state = StateManager.new_infinite(
+    PERIODS, AMPLITUDES, seed, 256,
+    canvas.height, canvas.width, 12, WATER_LEVEL, biomeThresholds(),
+  );
+  state.shift(-offsetX, -offsetY);
+  return state;
+}
+
+// a world from a greyscale PNG heightmap, read with the same range noise is saved with so saved noise comes back as it was
+function heightmapState(file: Uint8Array) {
+  const offsetX = Math.floor(canvas.width * 0.5);
+  const offsetY = 50;
+  const seed = BigInt(Math.floor(Math.random() * 2 ** 32));
+  noiseSeed = undefined;
+  const state = StateManager.from_heightmap_png(
+    file, 2 * NOISE_REACH, -NOISE_REACH, canvas.height, canvas.width, 12, seed, WATER_LEVEL, biomeThresholds(),
+  );
+  state.shift(-offsetX, -offsetY);
+  return state;
+}
+
+// a world from an ESRI ASCII grid elevation model, with missing cells as water
+function elevationState(text: string) {
+  const offsetX = Math.floor(canvas.width * 0.5);
+  const offsetY = 50;
+  const seed = BigInt(Math.floor(Math.random() * 2 ** 32));
+  noiseSeed = undefined;
+  const params = ElevationParams.new();
+  params.no_data = NoData.Water;
+  const state = StateManager.from_ascii_grid(
+    text, params, canvas.height, canvas.width, 12, seed, WATER_LEVEL, biomeThresholds(),
   );
   state.shift(-offsetX, -offsetY);
   return state;
@@ -33,10 +149,101 @@ function randomState() {
 
 const STEP_SIZE = 20;
 
+const TILE_SIZE = 16;
+// speckled tiles for the default materials, with grass and forest sides showing dirt below a strip of their top
+const TILE_COLORS: [string, number][] = [
+  ["grass", 0x5d9b3a], ["dirt", 0x8a6240], ["stone", 0x8c8c8c], ["sand", 0xdccb8c], ["snow", 0xf4f6fa],
+  ["water", 0x3a6fb4], ["forest", 0x2f6b2a], ["red sand", 0xd08c4f], ["tundra", 0x9aa58a], ["silt", 0x7d7462],
+];
+
+// an atlas with a tile per material, followed by side tiles for grass and forest; returns the atlas and each material's tiles
+function textureAtlas(): [Uint8Array, number, number, Map<string, [number, number, number]>] {
+  const sides = ["grass", "forest"];
+  const count = TILE_COLORS.length + sides.length;
+  const width = count * TILE_SIZE;
+  const data = new Uint8Array(width * TILE_SIZE * 4);
+  const fill = (tile: number, color: (x: number, y: number) => number) => {
+    for (let y = 0; y < TILE_SIZE; y++) {
+      for (let x = 0; x < TILE_SIZE; x++) {
+        const c = color(x, y);
+        // up to 12% darker or lighter per texel
+        const shade = 0.88 + Math.random() * 0.24;
+        const i = (y * width + tile * TILE_SIZE + x) * 4;
+        data[i] = Math.min(255, ((c >> 16) & 0xff) * shade);
+        data[i + 1] = Math.min(255, ((c >> 8) & 0xff) * shade);
+        data[i + 2] = Math.min(255, (c & 0xff) * shade);
+        data[i + 3] = 255;
+      }
+    }
+  };
+  const tiles = new Map<string, [number, number, number]>();
+  TILE_COLORS.forEach(([name, color], tile) => {
+    fill(tile, () => color);
+    tiles.set(name, [tile, tile, tile]);
+  });
+  const dirt = TILE_COLORS.find(([name]) => name === "dirt")![1];
+  sides.forEach((name, i) => {
+    const tile = TILE_COLORS.length + i;
+    const [top] = tiles.get(name)!;
+    fill(tile, (x, y) => y < 3 + (x * 7) % 3 ? TILE_COLORS[top][1] : dirt);
+    tiles.set(name, [top, tile, tile]);
+  });
+  return [data, width, TILE_SIZE, tiles];
+}
+
+const [ATLAS, ATLAS_WIDTH, ATLAS_HEIGHT, MATERIAL_TILES] = textureAtlas();
+
+// draws the default materials with the atlas, or with flat colors
+function setTextures(state: StateManager, textured: boolean) {
+  if (!textured) {
+    state.clear_texture_atlas();
+    return;
+  }
+  state.set_texture_atlas(ATLAS, ATLAS_WIDTH, ATLAS_HEIGHT, TILE_SIZE);
+  for (const [name, [top, left, right]] of MATERIAL_TILES) {
+    state.set_material_tiles(state.material_id(name)!, top, left, right);
+  }
+}
+
+// sunlight for an hour of the day, with the sun rising toward +x and setting toward -x
+function sunlight(hour: number) {
+  const light = Light.new();
+  const angle = Math.PI * (hour - 6) / 12;
+  light.sun_x = Math.cos(angle);
+  light.sun_y = 0.5;
+  light.sun_z = Math.sin(angle);
+  // redder and dimmer near the horizon
+  const height = Math.max(light.sun_z, 0);
+  const channel = (c: number) => Math.round(c * (0.4 + 0.6 * Math.sqrt(height)));
+  light.sun_color = (channel(255) << 16) | (channel(200 + 40 * height) << 8) | channel(160 + 80 * height);
+  return light;
+}
+
 init().then(() => {
   let state = randomState();
+  let depthBuffer = false;
+  let ambientOcclusion = true;
+  let waterAlpha = 0.6;
+  let hour: number | undefined = undefined;
+  let textured = false;
+  let antialiasing = 1;
+  // material placed by shift-clicking, picked with the number keys
+  const placeable = ["stone", "glass", "ice"];
+  let placing = placeable[0];
   render(state);
 
+  // carries the current view settings over to a newly generated world
+  function applySettings(state: StateManager) {
+    state.set_depth_buffer(depthBuffer);
+    state.set_ambient_occlusion(ambientOcclusion);
+    state.set_material_alpha(state.material_id("water")!, waterAlpha);
+    if (hour !== undefined) {
+      state.set_light(sunlight(hour));
+    }
+    setTextures(state, textured);
+    state.set_antialiasing(antialiasing);
+  }
+
   let needsRefresh = false;
   function requestMove(dir: 'up' | 'down' | 'left' | 'right') {
     switch(dir) {
@@ -73,11 +280,123 @@ init().then(() => {
         break;
       case " ":
         state = randomState();
+        applySettings(state);
+        render(state);
+        break;
+      case "i":
+        state = infiniteState();
+        applySettings(state);
+        render(state);
+        break;
+      case "t":
+        // toggle seeing through water
+        waterAlpha = waterAlpha < 1 ? 1 : 0.6;
+        state.set_material_alpha(state.material_id("water")!, waterAlpha);
+        render(state);
+        break;
+      case "q":
+        state.rotate(false);
+        render(state);
+        break;
+      case "e":
+        state.rotate(true);
+        render(state);
+        break;
+      case "l":
+        // step through the day, going back to the default light after sunset
+        hour = hour === undefined ? 7 : hour + 1;
+        if (hour > 17) {
+          hour = undefined;
+        }
+        state.set_light(hour === undefined ? Light.new() : sunlight(hour));
+        render(state);
+        break;
+      case "o":
+        ambientOcclusion = !ambientOcclusion;
+        state.set_ambient_occlusion(ambientOcclusion);
+        render(state);
+        break;
+      case "x":
+        textured = !textured;
+        setTextures(state, textured);
+        render(state);
+        break;
+      case "z": {
+        // cycle through no anti-aliasing and 2x2 and 3x3 supersampling
+        antialiasing = antialiasing % 3 + 1;
+        const multiplier = state.set_antialiasing(antialiasing);
+        const time0 = Date.now();
+        state.draw();
+        console.log(`Anti-aliasing ${antialiasing > 1 ? `at ${antialiasing}x${antialiasing}` : 'off'}: pixel multiplier ${multiplier}, drawn in ${Date.now() - time0}ms`);
+        render(state, false);
+        break;
+      }
+      case "1":
+      case "2":
+      case "3":
+        placing = placeable[Number(e.key) - 1];
+        console.log(`Placing ${placing}`);
+        break;
+      case "p":
+        saveScreenshot(state);
+        break;
+      case "h":
+      case "H":
+        // shift-H saves the noise and each octave of it as well
+        saveWorld(state, e.shiftKey);
+        break;
+      case "b":
+        depthBuffer = !depthBuffer;
+        console.log(`Depth buffer ${depthBuffer ? 'on' : 'off'}`);
+        state.set_depth_buffer(depthBuffer);
         render(state);
         break;
     }
   });
 
+  // click removes the block under the cursor, shift-click places one against the clicked face
+  canvas.addEventListener('click', (e) => {
+    const rect = canvas.getBoundingClientRect();
+    const pick = state.pick(Math.floor(e.clientX - rect.left), Math.floor(e.clientY - rect.top));
+    if (pick === undefined) {
+      return;
+    }
+    if (e.shiftKey) {
+      state.place_block(pick.x + pick.nx, pick.y + pick.ny, pick.z + pick.nz, state.material_id(placing)!);
+    } else {
+      state.remove_block(pick.x, pick.y, pick.z);
+    }
+    render(state);
+  });
+
+  // dropping a greyscale PNG or an ESRI ASCII grid (.asc) on the canvas builds a world from it
+  canvas.addEventListener('dragover', (e) => e.preventDefault());
+  canvas.addEventListener('drop', async (e) => {
+    e.preventDefault();
+    const file = e.dataTransfer?.files[0];
+    if (file === undefined) {
+      return;
+    }
+    try {
+      state = file.name.toLowerCase().endsWith('.asc')
+        ? elevationState(await file.text())
+        : heightmapState(new Uint8Array(await file.arrayBuffer()));
+    } catch (error) {
+      console.error(`Can't load ${file.name}: ${error}`);
+      return;
+    }
+    applySettings(state);
+    render(state);
+  });
+
+  // scrolling zooms in and out around the cursor
+  canvas.addEventListener('wheel', (e) => {
+    e.preventDefault();
+    const rect = canvas.getBoundingClientRect();
+    state.zoom(e.deltaY < 0 ? 1.25 : 0.8, e.clientX - rect.left, e.clientY - rect.top);
+    render(state);
+  });
+
   setInterval(() => {
     if (needsRefresh) {
       needsRefresh = false;
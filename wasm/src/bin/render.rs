@@ -0,0 +1,195 @@
+//! Renders a generated world, or one from an elevation model, to a PNG file without a browser.
+//!
+//! e.g. `cargo run --release --bin render -- --size 200x200 --seed 7 --rotation 1 --output world.png`
+
+use std::{collections::HashMap, process, str::FromStr, time::Instant};
+
+use wasm::{heightmap_png, hillshade_png, BiomeThresholds, ElevationParams, ErosionParams, NoData, StateManager};
+
+const USAGE: &str = "usage: render [options]
+  --size WxH            world size in blocks (default 150x150, or the elevation model's size)
+  --image WxH           image size in pixels (default 1200x900)
+  --periods P,...       perlin noise periods in blocks, one per layer (default 20,8)
+  --amplitudes A,...    perlin noise amplitudes in blocks, one per layer (default 9,7)
+  --seed N              seed for terrain, climate and erosion (default 0)
+  --scale S             pixels per block edge (default 12)
+  --origin X,Y          screen space point drawn at the top left of the image (default centers the world)
+  --rotation R          quarter turns clockwise, 0 to 3 (default 0)
+  --water-level Z       height water fills basins to (default -2)
+  --erosion N           erosion droplets (default 40000)
+  --dem PATH            build the world from an ESRI ASCII grid (.asc) rather than noise
+  --exaggeration E      multiplier on elevation model heights (default 1)
+  --cell-width W        width of the elevation model's cells in the units of its heights, for grids in degrees
+                        (default the grid's cellsize)
+  --base B              elevation drawn at z = 0 (default 0)
+  --no-data MODE        draw cells without data as hole or water (default hole)
+  --antialiasing F      draw F by F pixels per image pixel, 1 to 4 (default 1)
+  --output PATH         PNG file to write (default world.png)
+  --noise-png PREFIX    also write the noise before erosion, and each octave of it, as 16 bit heightmaps and
+                        hillshades named PREFIX-all.png, PREFIX-all-hillshade.png, PREFIX-octave1.png, ...";
+
+fn fail(message: &str) -> ! {
+    eprintln!("{}\n{}", message, USAGE);
+    process::exit(2);
+}
+
+// values given for each --option
+fn options() -> HashMap<String, String> {
+    let mut options = HashMap::new();
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        if arg == "--help" || arg == "-h" {
+            println!("{}", USAGE);
+            process::exit(0);
+        }
+        let name = match arg.strip_prefix("--") {
+            Some(name) if USAGE.contains(&format!("--{} ", name)) => name.to_string(),
+            _ => fail(&format!("unknown option {}", arg)),
+        };
+        let value = args.next().unwrap_or_else(|| fail(&format!("missing value for --{}", name)));
+        options.insert(name, value);
+    }
+    options
+}
+
+// values separated by sep, e.g. "20,8"
+fn parse_list<T: FromStr>(value: &str, sep: char) -> Option<Vec<T>> {
+    value.split(sep).map(|v| v.trim().parse().ok()).collect()
+}
+
+fn get<T: FromStr>(options: &HashMap<String, String>, name: &str, default: T) -> T {
+    match options.get(name) {
+        Some(value) => value.parse().unwrap_or_else(|_| fail(&format!("invalid --{} {}", name, value))),
+        None => default,
+    }
+}
+
+fn get_list<T: FromStr>(options: &HashMap<String, String>, name: &str, sep: char, default: Vec<T>) -> Vec<T> {
+    match options.get(name) {
+        Some(value) => parse_list(value, sep).unwrap_or_else(|| fail(&format!("invalid --{} {}", name, value))),
+        None => default,
+    }
+}
+
+// two values separated by sep
+fn get_pair<T: FromStr + Copy>(options: &HashMap<String, String>, name: &str, sep: char) -> Option<[T; 2]> {
+    let pair = get_list(options, name, sep, vec![]);
+    match pair.as_slice() {
+        [] => None,
+        &[a, b] => Some([a, b]),
+        _ => fail(&format!("--{} takes two values", name)),
+    }
+}
+
+// writes the noise and each of its octaves as heightmap and hillshade PNG files starting with prefix
+fn write_noise(prefix: &str, width: usize, height: usize, periods: &[usize], amplitudes: &[f32], seed: u64, water_level: f32) {
+    let octaves = (0..periods.len()).map(|i| (format!("octave{}", i + 1), Some(i)));
+    for (name, octave) in std::iter::once(("all".to_string(), None)).chain(octaves) {
+        let (periods, amplitudes) = (periods.to_vec(), amplitudes.to_vec());
+        let files = [
+            (format!("{}-{}.png", prefix, name), heightmap_png(height, width, periods.clone(), amplitudes.clone(), seed, octave)),
+            (format!("{}-{}-hillshade.png", prefix, name), hillshade_png(height, width, periods, amplitudes, seed, water_level, octave)),
+        ];
+        for (path, file) in files {
+            let file = file.unwrap_or_else(|e| fail(&e));
+            if let Err(e) = std::fs::write(&path, file) {
+                eprintln!("can't write {}: {}", path, e);
+                process::exit(1);
+            }
+        }
+    }
+    let reach: f32 = amplitudes.iter().map(|a| a.abs()).sum();
+    eprintln!(
+        "wrote {}-*.png; {}-all.png reads back with a vertical scale of {} and an offset of {}",
+        prefix, prefix, 2. * reach, -reach,
+    );
+}
+
+fn main() {
+    let options = options();
+    let size = get_pair(&options, "size", 'x');
+    let [pixel_width, pixel_height] = get_pair(&options, "image", 'x').unwrap_or([1200, 900]);
+    if size.is_some_and(|[width, height]: [usize; 2]| width < 2 || height < 2) {
+        fail("--size needs at least 2 blocks along each side");
+    }
+    if pixel_width < 2 || pixel_height < 2 {
+        fail("--image needs at least 2 pixels along each side");
+    }
+    let periods = get_list(&options, "periods", ',', vec![20, 8]);
+    let amplitudes = get_list(&options, "amplitudes", ',', vec![9., 7.]);
+    if periods.len() != amplitudes.len() || periods.contains(&0) {
+        fail("--periods and --amplitudes need one nonzero period per amplitude");
+    }
+    let seed = get(&options, "seed", 0);
+    let scale = get(&options, "scale", 12.);
+    let rotation = get(&options, "rotation", 0u8);
+    let water_level = get(&options, "water-level", -2);
+    let antialiasing = get(&options, "antialiasing", 1);
+    let output = get(&options, "output", "world.png".to_string());
+    let mut erosion = ErosionParams::new();
+    erosion.iterations = get(&options, "erosion", 40000);
+    erosion.seed = seed;
+
+    let start = Instant::now();
+    let biomes = BiomeThresholds::new();
+    let (mut state, [width, height]) = match options.get("dem") {
+        Some(path) => {
+            let text = std::fs::read_to_string(path).unwrap_or_else(|e| {
+                eprintln!("can't read {}: {}", path, e);
+                process::exit(1);
+            });
+            let mut params = ElevationParams::new();
+            params.exaggeration = get(&options, "exaggeration", 1.);
+            params.cell_width = get(&options, "cell-width", 0.);
+            params.base = get(&options, "base", 0.);
+            params.no_data = match get(&options, "no-data", "hole".to_string()).as_str() {
+                "hole" => NoData::Hole,
+                "water" => NoData::Water,
+                other => fail(&format!("invalid --no-data {}", other)),
+            };
+            // without --size the grid keeps its own size
+            if let Some([width, height]) = size {
+                [params.cols, params.rows] = [width, height];
+            }
+            let state = StateManager::from_ascii_grid(
+                &text, &params, pixel_height, pixel_width, scale, seed, water_level, &biomes,
+            ).unwrap_or_else(|e| {
+                eprintln!("can't load {}: {}", path, e);
+                process::exit(1);
+            });
+            let size = [state.world_cols(), state.world_rows()];
+            (state, size)
+        }
+        None => {
+            let [width, height] = size.unwrap_or([150, 150]);
+            if let Some(prefix) = options.get("noise-png") {
+                write_noise(prefix, width, height, &periods, &amplitudes, seed, water_level as f32);
+            }
+            let state = StateManager::new(
+                height, width, periods, amplitudes, pixel_height, pixel_width, scale, seed, water_level, &biomes, &erosion,
+            );
+            (state, [width, height])
+        }
+    };
+    eprintln!("built {}x{} world in {:?}", width, height, start.elapsed());
+    for _ in 0..rotation % 4 {
+        state.rotate(true);
+    }
+    match get_pair(&options, "origin", ',') {
+        Some([x, y]) => state.set_origin(x, y),
+        None => state.look_at(width as f32 / 2., height as f32 / 2.),
+    }
+    state.set_antialiasing(antialiasing);
+
+    let start = Instant::now();
+    state.draw();
+    eprintln!("drew {}x{} image in {:?}", pixel_width, pixel_height, start.elapsed());
+    let png = state.get_png().unwrap_or_else(|e| {
+        eprintln!("can't save {}: {}", output, e);
+        process::exit(1);
+    });
+    if let Err(e) = std::fs::write(&output, png) {
+        eprintln!("can't write {}: {}", output, e);
+        process::exit(1);
+    }
+}
@@ -0,0 +1,221 @@
+use wasm_bindgen::prelude::*;
+
+use crate::{
+    materials::{MaterialId, DIRT, FOREST, GRASS, RED_SAND, SAND, SNOW, STONE, TUNDRA},
+    terrain::{perlin_layers, Heightmap, NoiseLayers},
+};
+
+// climate fields vary over larger distances than terrain, with values mostly within -1 to 1
+const CLIMATE_PERIODS: [usize; 2] = [48, 16];
+const CLIMATE_AMPLITUDES: [f32; 2] = [1., 0.3];
+// mixed into the terrain seed so climate fields don't just follow the terrain
+const MOISTURE_SALT: u64 = 0x6d6f6973;
+const TEMPERATURE_SALT: u64 = 0x74656d70;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Biome {
+    Beach,
+    Grassland,
+    Forest,
+    Desert,
+    Tundra,
+    Snowcap,
+    // too steep for anything but bare rock
+    Cliff,
+}
+
+impl Biome {
+    // material for the top block of columns in this biome
+    pub fn surface(&self) -> MaterialId {
+        match self {
+            Biome::Beach => SAND,
+            Biome::Grassland => GRASS,
+            Biome::Forest => FOREST,
+            Biome::Desert => RED_SAND,
+            Biome::Tundra => TUNDRA,
+            Biome::Snowcap => SNOW,
+            Biome::Cliff => STONE,
+        }
+    }
+
+    // material for the few blocks just under the surface
+    pub fn subsurface(&self) -> MaterialId {
+        match self {
+            Biome::Beach => SAND,
+            Biome::Desert => RED_SAND,
+            Biome::Cliff => STONE,
+            _ => DIRT,
+        }
+    }
+}
+
+// cutoffs used to decide which biome a column belongs to
+// heights and slopes are in blocks; moisture and temperature are mostly within -1 to 1
+#[wasm_bindgen]
+#[derive(Clone, Copy, Debug)]
+pub struct BiomeThresholds {
+    // surfaces at or below this height are beach
+    pub beach_height: f32,
+    // surfaces at or above this height are snow-capped
+    pub snow_height: f32,
+    // height difference to a neighbouring column at or above which the surface is bare rock
+    pub cliff_slope: f32,
+    // how much the temperature drops per block of height
+    pub lapse_rate: f32,
+    // temperature below which the surface is tundra
+    pub cold: f32,
+    // temperature above which dry areas become desert
+    pub hot: f32,
+    // moisture below which hot areas become desert
+    pub dry: f32,
+    // moisture above which the surface is forest
+    pub wet: f32,
+}
+
+#[wasm_bindgen]
+impl BiomeThresholds {
+    pub fn new() -> Self {
+        Self {
+            beach_height: -3.,
+            snow_height: 8.,
+            cliff_slope: 3.,
+            lapse_rate: 0.04,
+            cold: -0.3,
+            hot: 0.2,
+            dry: -0.1,
+            wet: 0.15,
+        }
+    }
+}
+
+impl Default for BiomeThresholds {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl BiomeThresholds {
+    pub fn classify(&self, height: f32, slope: f32, moisture: f32, temperature: f32) -> Biome {
+        let temperature = temperature - self.lapse_rate * height.max(0.);
+        if slope >= self.cliff_slope {
+            Biome::Cliff
+        } else if height >= self.snow_height {
+            Biome::Snowcap
+        } else if height <= self.beach_height {
+            Biome::Beach
+        } else if temperature < self.cold {
+            Biome::Tundra
+        } else if temperature > self.hot && moisture < self.dry {
+            Biome::Desert
+        } else if moisture > self.wet {
+            Biome::Forest
+        } else {
+            Biome::Grassland
+        }
+    }
+}
+
+// moisture and temperature over the same area as some heightmap
+pub struct Climate {
+    pub moisture: Heightmap,
+    pub temperature: Heightmap,
+}
+
+impl Climate {
+    // climate for a heightmap generated by `perlin_layers`
+    pub fn generate(rows: usize, cols: usize, seed: u64) -> Self {
+        let field = |salt: u64| perlin_layers(
+            rows, cols, CLIMATE_PERIODS.to_vec(), CLIMATE_AMPLITUDES.to_vec(), seed ^ salt,
+        );
+        Self {
+            moisture: field(MOISTURE_SALT),
+            temperature: field(TEMPERATURE_SALT),
+        }
+    }
+
+    // climate for part of an unbounded scene, matching `NoiseLayers::heightmap`
+    pub fn sample(x0: i32, y0: i32, rows: usize, cols: usize, seed: u64) -> Self {
+        let field = |salt: u64| NoiseLayers::new(
+            CLIMATE_PERIODS.to_vec(), CLIMATE_AMPLITUDES.to_vec(), seed ^ salt,
+        ).heightmap(x0, y0, rows, cols);
+        Self {
+            moisture: field(MOISTURE_SALT),
+            temperature: field(TEMPERATURE_SALT),
+        }
+    }
+}
+
+// biome of every cell in a heightmap
+// slope is the largest height difference to one of the four neighbouring cells within the heightmap
+pub fn classify(h: &Heightmap, climate: &Climate, thresholds: &BiomeThresholds) -> Vec<Biome> {
+    let height_at = |i: usize, j: usize| h.data[i * h.cols + j].trunc();
+    (0..h.rows * h.cols).map(
+        |idx| {
+            let (i, j) = (idx / h.cols, idx % h.cols);
+            let height = height_at(i, j);
+            let mut slope = 0f32;
+            if i > 0 { slope = slope.max((height - height_at(i - 1, j)).abs()) }
+            if j > 0 { slope = slope.max((height - height_at(i, j - 1)).abs()) }
+            if i + 1 < h.rows { slope = slope.max((height - height_at(i + 1, j)).abs()) }
+            if j + 1 < h.cols { slope = slope.max((height - height_at(i, j + 1)).abs()) }
+            thresholds.classify(height, slope, climate.moisture.data[idx], climate.temperature.data[idx])
+        }
+    ).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn thresholds_pick_each_biome() {
+        let t = BiomeThresholds::new();
+        // temperate climate: neither cold, hot, dry nor wet
+        let (moisture, temperature) = (0., 0.);
+        assert_eq!(t.classify(0., 0., moisture, temperature), Biome::Grassland);
+        // cutoffs are inclusive for cliffs, snow and beaches
+        assert_eq!(t.classify(0., 3., moisture, temperature), Biome::Cliff);
+        assert_eq!(t.classify(0., 2.9, moisture, temperature), Biome::Grassland);
+        assert_eq!(t.classify(8., 0., moisture, 1.), Biome::Snowcap);
+        assert_eq!(t.classify(7.9, 0., moisture, 1.), Biome::Grassland);
+        assert_eq!(t.classify(-3., 0., moisture, temperature), Biome::Beach);
+        assert_eq!(t.classify(-2.9, 0., moisture, temperature), Biome::Grassland);
+        // and exclusive for climate
+        assert_eq!(t.classify(0., 0., moisture, -0.31), Biome::Tundra);
+        assert_eq!(t.classify(0., 0., moisture, -0.3), Biome::Grassland);
+        assert_eq!(t.classify(0., 0., -0.11, 0.21), Biome::Desert);
+        assert_eq!(t.classify(0., 0., -0.1, 0.21), Biome::Grassland);
+        assert_eq!(t.classify(0., 0., -0.11, 0.2), Biome::Grassland);
+        assert_eq!(t.classify(0., 0., 0.16, temperature), Biome::Forest);
+        assert_eq!(t.classify(0., 0., 0.15, temperature), Biome::Grassland);
+    }
+
+    #[test]
+    fn thresholds_apply_in_order() {
+        let t = BiomeThresholds::new();
+        // steepness beats height, height beats climate, and cold beats dry or wet
+        assert_eq!(t.classify(10., 5., 0., 0.), Biome::Cliff);
+        assert_eq!(t.classify(-5., 5., 0., 0.), Biome::Cliff);
+        assert_eq!(t.classify(10., 0., 1., 1.), Biome::Snowcap);
+        assert_eq!(t.classify(-5., 0., -1., 1.), Biome::Beach);
+        assert_eq!(t.classify(0., 0., 1., -1.), Biome::Tundra);
+        assert_eq!(t.classify(0., 0., 1., 1.), Biome::Forest);
+        // it gets colder with height, by lapse_rate per block, but not below 0
+        assert_eq!(t.classify(5., 0., 0., -0.15), Biome::Tundra);
+        assert_eq!(t.classify(-2., 0., 0., -0.29), Biome::Grassland);
+        assert_eq!(t.classify(0., 0., -0.5, 0.3), Biome::Desert);
+        assert_eq!(t.classify(5., 0., -0.5, 0.3), Biome::Grassland);
+    }
+
+    #[test]
+    fn slopes_come_from_neighbouring_columns() {
+        // a step of 3 blocks between the second and third columns, with heights truncated to whole blocks
+        let h = Heightmap { data: vec![0., 0.5, 3.9, 3.], rows: 1, cols: 4 };
+        let climate = Climate {
+            moisture: Heightmap { data: vec![0.; 4], rows: 1, cols: 4 },
+            temperature: Heightmap { data: vec![0.; 4], rows: 1, cols: 4 },
+        };
+        let biomes = classify(&h, &climate, &BiomeThresholds::new());
+        assert_eq!(biomes, [Biome::Grassland, Biome::Cliff, Biome::Cliff, Biome::Grassland]);
+    }
+}
@@ -0,0 +1,203 @@
+use wasm_bindgen::prelude::*;
+
+use crate::terrain::Heightmap;
+
+// what ESRI grids mark cells without data with when they don't say
+const DEFAULT_NODATA: f32 = -9999.;
+
+// what to draw where an elevation model has no data
+#[wasm_bindgen]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum NoData {
+    // leave the columns out
+    Hole,
+    // fill the columns with water, as is common for seas and lakes in elevation models
+    Water,
+}
+
+// settings for turning an elevation model into blocks
+#[wasm_bindgen]
+#[derive(Clone, Copy, Debug)]
+pub struct ElevationParams {
+    // size to resample the grid to, in cells; 0 keeps the grid's own size along that axis
+    pub rows: usize,
+    pub cols: usize,
+    // multiplier on heights; at 1 a block is as tall as it is wide
+    pub exaggeration: f32,
+    // width of the grid's cells in the units of its elevations; 0 uses the grid's cellsize, which assumes they match
+    // grids in degrees need this set, e.g. to about 92.6 for 3 arc-second cells with elevations in metres
+    pub cell_width: f32,
+    // elevation drawn at z = 0, in the grid's units
+    pub base: f32,
+    pub no_data: NoData,
+}
+
+#[wasm_bindgen]
+impl ElevationParams {
+    pub fn new() -> Self {
+        Self {
+            rows: 0,
+            cols: 0,
+            exaggeration: 1.,
+            cell_width: 0.,
+            base: 0.,
+            no_data: NoData::Hole,
+        }
+    }
+}
+
+impl Default for ElevationParams {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// elevations over a regular grid, from north to south and west to east, with NaN where there's no data
+pub struct ElevationGrid {
+    pub data: Vec<f32>,
+    pub rows: usize,
+    pub cols: usize,
+    // distance between neighbouring cells, in the same units as the elevations
+    pub cellsize: f32,
+}
+
+impl ElevationGrid {
+    // parses an ESRI ASCII grid: a header of ncols, nrows, cellsize and optional corner and NODATA_value lines,
+    // followed by nrows rows of ncols elevations
+    pub fn parse_asc(text: &str) -> Result<Self, String> {
+        let mut tokens = text.split_whitespace().peekable();
+        let (mut rows, mut cols, mut cellsize, mut nodata) = (None, None, None, DEFAULT_NODATA);
+        // header lines are a name then a number; the elevations start at the first token that isn't a name
+        while let Some(name) = tokens.next_if(|t| t.parse::<f32>().is_err()) {
+            let value = tokens.next().ok_or(format!("Missing value for {}", name))?;
+            let number = |value: &str| value.parse::<f32>().map_err(|_| format!("Invalid {}: {}", name, value));
+            match name.to_ascii_lowercase().as_str() {
+                "ncols" => cols = Some(value.parse::<usize>().map_err(|_| format!("Invalid ncols: {}", value))?),
+                "nrows" => rows = Some(value.parse::<usize>().map_err(|_| format!("Invalid nrows: {}", value))?),
+                "cellsize" => cellsize = Some(number(value)?),
+                "nodata_value" => nodata = number(value)?,
+                // where the grid is on the map doesn't change how it looks
+                "xllcorner" | "yllcorner" | "xllcenter" | "yllcenter" => {
+                    number(value)?;
+                }
+                _ => return Err(format!("Unknown header field {}", name)),
+            }
+        }
+        let (rows, cols) = (rows.ok_or("Missing nrows")?, cols.ok_or("Missing ncols")?);
+        let cellsize = cellsize.filter(|c| *c > 0.).ok_or("Missing or non-positive cellsize")?;
+        if rows == 0 || cols == 0 {
+            return Err("Grid has no cells".to_string());
+        }
+        let data = tokens
+            .map(|t| match t.parse::<f32>() {
+                Ok(v) if v == nodata => Ok(f32::NAN),
+                Ok(v) => Ok(v),
+                Err(_) => Err(format!("Invalid elevation: {}", t)),
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        if data.len() != rows * cols {
+            return Err(format!("Expected {} elevations, found {}", rows * cols, data.len()));
+        }
+        Ok(Self { data, rows, cols, cellsize })
+    }
+
+    // the grid resampled to rows by cols cells, interpolating between the nearest cells with data
+    // cells whose nearest cell has no data have none either, so holes keep their shape
+    // the cell size changes to keep the grid covering the same area
+    pub fn resampled(&self, rows: usize, cols: usize) -> Self {
+        let (row_step, col_step) = (self.rows as f32 / rows as f32, self.cols as f32 / cols as f32);
+        let at = |i: usize, j: usize| self.data[i * self.cols + j];
+        let mut data = Vec::with_capacity(rows * cols);
+        for i in 0..rows {
+            // position of the new cell's center among the old cells' centers
+            let y = ((i as f32 + 0.5) * row_step - 0.5).clamp(0., (self.rows - 1) as f32);
+            let (i0, wy) = (y.floor() as usize, y.fract());
+            let i1 = (i0 + 1).min(self.rows - 1);
+            for j in 0..cols {
+                let x = ((j as f32 + 0.5) * col_step - 0.5).clamp(0., (self.cols - 1) as f32);
+                let (j0, wx) = (x.floor() as usize, x.fract());
+                let j1 = (j0 + 1).min(self.cols - 1);
+                let nearest = at(if wy < 0.5 { i0 } else { i1 }, if wx < 0.5 { j0 } else { j1 });
+                if nearest.is_nan() {
+                    data.push(f32::NAN);
+                    continue;
+                }
+                let corners = [(i0, j0, (1. - wy) * (1. - wx)), (i0, j1, (1. - wy) * wx), (i1, j0, wy * (1. - wx)), (i1, j1, wy * wx)];
+                let (sum, weight) = corners.iter()
+                    .filter(|(ci, cj, _)| !at(*ci, *cj).is_nan())
+                    .fold((0., 0.), |(sum, weight), (ci, cj, w)| (sum + at(*ci, *cj) * w, weight + w));
+                data.push(if weight > 0. { sum / weight } else { nearest });
+            }
+        }
+        let cellsize = self.cellsize * (row_step + col_step) / 2.;
+        Self { data, rows, cols, cellsize }
+    }
+
+    // heights in blocks, each block as wide as a cell, keeping NaN where there's no data
+    pub fn to_heightmap(&self, exaggeration: f32, base: f32) -> Heightmap {
+        let data = self.data.iter().map(|e| (e - base) / self.cellsize * exaggeration).collect();
+        Heightmap { data, rows: self.rows, cols: self.cols }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const GRID: &str = "ncols 4
+nrows 3
+xllcorner 500000.0
+yllcorner 4100000.0
+cellsize 10
+NODATA_value -9999
+10 20 30 40
+10 -9999 30 40
+10 20 30 40.5
+";
+
+    #[test]
+    fn parses_ascii_grids() {
+        let grid = ElevationGrid::parse_asc(GRID).unwrap();
+        assert_eq!((grid.rows, grid.cols, grid.cellsize), (3, 4, 10.));
+        assert_eq!(grid.data[..4], [10., 20., 30., 40.]);
+        assert!(grid.data[5].is_nan());
+        assert_eq!(grid.data[11], 40.5);
+        let h = grid.to_heightmap(2., 10.);
+        assert_eq!(h.data[..4], [0., 2., 4., 6.]);
+        // headers are case-insensitive, and NODATA_value is optional
+        let grid = ElevationGrid::parse_asc("NCOLS 2\nNROWS 1\nCELLSIZE 1\n-9999 3").unwrap();
+        assert!(grid.data[0].is_nan());
+        assert!(ElevationGrid::parse_asc("ncols 2\nnrows 2\ncellsize 1\n1 2 3").is_err());
+        assert!(ElevationGrid::parse_asc("ncols 2\nnrows 1\n1 2").is_err());
+        assert!(ElevationGrid::parse_asc("ncols 2\nnrows 1\ncellsize 1\n1 x").is_err());
+    }
+
+    #[test]
+    fn cell_width_sets_the_scale_of_heights() {
+        // 3 arc-second cells, about 92.6m wide, with elevations in metres
+        let grid = ElevationGrid::parse_asc("ncols 2\nnrows 1\ncellsize 0.000833\n0 1000").unwrap();
+        assert!(grid.to_heightmap(1., 0.).check_within_world().is_err());
+        let grid = ElevationGrid { cellsize: 92.6, ..grid };
+        let h = grid.to_heightmap(1., 0.);
+        assert!(h.check_within_world().is_ok());
+        assert_eq!(h.data[1].round(), 11.);
+    }
+
+    #[test]
+    fn resampling_keeps_holes_and_area() {
+        let grid = ElevationGrid::parse_asc(GRID).unwrap();
+        let same = grid.resampled(3, 4);
+        assert_eq!(same.data.iter().map(|e| e.is_nan()).collect::<Vec<_>>(), grid.data.iter().map(|e| e.is_nan()).collect::<Vec<_>>());
+        assert_eq!(same.data[0], 10.);
+        let fine = grid.resampled(6, 8);
+        assert_eq!(fine.cellsize, 5.);
+        // the hole covers the same part of the grid
+        assert!(fine.data[2 * 8 + 2].is_nan() && fine.data[3 * 8 + 3].is_nan());
+        assert!(!fine.data[2 * 8 + 4].is_nan());
+        // and values between cells are interpolated
+        assert!(fine.data[5] > 30. && fine.data[5] < 40.);
+        let coarse = grid.resampled(1, 2);
+        assert_eq!(coarse.cellsize, 25.);
+        assert!(!coarse.data[1].is_nan());
+    }
+}
@@ -0,0 +1,231 @@
+use rand::{Rng, SeedableRng};
+use rand_chacha::ChaCha8Rng;
+use wasm_bindgen::prelude::*;
+
+use crate::terrain::Heightmap;
+
+// how many steps a droplet takes before it's dropped
+const MAX_LIFETIME: usize = 30;
+// fraction of the missing sediment a droplet picks up per step
+const EROSION_RATE: f32 = 0.3;
+// how strongly going downhill speeds up a droplet
+const GRAVITY: f32 = 4.;
+// keeps droplets carrying a little sediment even on flat ground
+const MIN_CAPACITY: f32 = 0.01;
+
+// settings for droplet-based hydraulic erosion
+#[wasm_bindgen]
+#[derive(Clone, Copy, Debug)]
+pub struct ErosionParams {
+    // number of droplets to simulate; 0 disables erosion
+    pub iterations: usize,
+    // from 0 to 1; how much droplets keep their direction rather than following the slope
+    pub inertia: f32,
+    // multiplier on how much sediment a droplet can carry, which grows with speed, water and slope
+    pub capacity: f32,
+    // fraction of excess sediment a droplet drops per step
+    pub deposition: f32,
+    // fraction of a droplet's water lost per step
+    pub evaporation: f32,
+    pub seed: u64,
+    // number of thermal weathering passes run after the droplets; 0 disables it
+    pub thermal_iterations: usize,
+    // steepest height difference between neighbouring cells that weathering leaves alone
+    pub talus: f32,
+}
+
+#[wasm_bindgen]
+impl ErosionParams {
+    pub fn new() -> Self {
+        Self {
+            iterations: 20000,
+            inertia: 0.05,
+            capacity: 4.,
+            deposition: 0.3,
+            evaporation: 0.02,
+            seed: 0,
+            thermal_iterations: 10,
+            talus: 2.,
+        }
+    }
+}
+
+impl Default for ErosionParams {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// how much material erosion removed from and added to each cell of a heightmap
+pub struct ErosionRecord {
+    pub deposited: Heightmap,
+    pub eroded: Heightmap,
+}
+
+impl ErosionRecord {
+    fn new(rows: usize, cols: usize) -> Self {
+        let empty = || Heightmap { data: vec![0.; rows * cols], rows, cols };
+        Self { deposited: empty(), eroded: empty() }
+    }
+
+    // net height added to cell idx
+    pub fn sediment(&self, idx: usize) -> f32 {
+        self.deposited.data[idx] - self.eroded.data[idx]
+    }
+}
+
+// height and gradient at a point, interpolated from the four surrounding cells
+fn height_and_gradient(h: &Heightmap, x: f32, y: f32) -> (f32, f32, f32) {
+    let (cx, cy) = (x as usize, y as usize);
+    let (u, v) = (x - cx as f32, y - cy as f32);
+    let idx = cy * h.cols + cx;
+    let nw = h.data[idx];
+    let ne = h.data[idx + 1];
+    let sw = h.data[idx + h.cols];
+    let se = h.data[idx + h.cols + 1];
+    let gx = (ne - nw) * (1. - v) + (se - sw) * v;
+    let gy = (sw - nw) * (1. - u) + (se - ne) * u;
+    let height = nw * (1. - u) * (1. - v) + ne * u * (1. - v) + sw * (1. - u) * v + se * u * v;
+    (height, gx, gy)
+}
+
+// adds amount to the four cells around (x, y), split by bilinear weights
+fn spread(data: &mut [f32], cols: usize, x: f32, y: f32, amount: f32) {
+    let (cx, cy) = (x as usize, y as usize);
+    let (u, v) = (x - cx as f32, y - cy as f32);
+    let idx = cy * cols + cx;
+    data[idx] += amount * (1. - u) * (1. - v);
+    data[idx + 1] += amount * u * (1. - v);
+    data[idx + cols] += amount * (1. - u) * v;
+    data[idx + cols + 1] += amount * u * v;
+}
+
+impl Heightmap {
+    // carves valleys by simulating water droplets that pick up material going downhill and drop it where they slow down
+    pub fn erode(&mut self, params: &ErosionParams) -> ErosionRecord {
+        let mut record = ErosionRecord::new(self.rows, self.cols);
+        if self.rows < 2 || self.cols < 2 {
+            return record;
+        }
+        let mut rng = ChaCha8Rng::seed_from_u64(params.seed);
+        let (max_x, max_y) = ((self.cols - 1) as f32, (self.rows - 1) as f32);
+        for _ in 0..params.iterations {
+            let mut x = rng.gen_range(0. ..max_x);
+            let mut y = rng.gen_range(0. ..max_y);
+            let (mut dx, mut dy) = (0f32, 0f32);
+            let (mut speed, mut water, mut sediment) = (1f32, 1f32, 0f32);
+            for _ in 0..MAX_LIFETIME {
+                let (height, gx, gy) = height_and_gradient(self, x, y);
+                dx = dx * params.inertia - gx * (1. - params.inertia);
+                dy = dy * params.inertia - gy * (1. - params.inertia);
+                let len = (dx * dx + dy * dy).sqrt();
+                if len == 0. {
+                    break;
+                }
+                dx /= len;
+                dy /= len;
+                let (new_x, new_y) = (x + dx, y + dy);
+                if new_x < 0. || new_y < 0. || new_x >= max_x || new_y >= max_y {
+                    break;
+                }
+                let dh = height_and_gradient(self, new_x, new_y).0 - height;
+                let capacity = (-dh * speed * water * params.capacity).max(MIN_CAPACITY);
+                if sediment > capacity || dh > 0. {
+                    // going uphill fills in the pit behind the droplet; otherwise drop part of the excess
+                    let amount = if dh > 0. { dh.min(sediment) } else { (sediment - capacity) * params.deposition };
+                    sediment -= amount;
+                    spread(&mut self.data, self.cols, x, y, amount);
+                    spread(&mut record.deposited.data, self.cols, x, y, amount);
+                } else {
+                    // never dig deeper than the drop to the next position
+                    let amount = ((capacity - sediment) * EROSION_RATE).min(-dh);
+                    sediment += amount;
+                    spread(&mut self.data, self.cols, x, y, -amount);
+                    spread(&mut record.eroded.data, self.cols, x, y, amount);
+                }
+                speed = (speed * speed - dh * GRAVITY).max(0.).sqrt();
+                water *= 1. - params.evaporation;
+                x = new_x;
+                y = new_y;
+            }
+        }
+
+        record
+    }
+
+    // knocks down cells that stand more than `talus` above a neighbour, sliding the excess onto the lower neighbours
+    // each pass moves half the excess over the steepest neighbour, split between all neighbours over the talus
+    pub fn weather(&mut self, talus: f32, iterations: usize) {
+        let (rows, cols) = (self.rows, self.cols);
+        let mut change = vec![0f32; rows * cols];
+        for _ in 0..iterations {
+            for idx in 0..rows * cols {
+                let (i, j) = (idx / cols, idx % cols);
+                let neighbors = [
+                    (i > 0).then(|| idx - cols),
+                    (i + 1 < rows).then(|| idx + cols),
+                    (j > 0).then(|| idx - 1),
+                    (j + 1 < cols).then(|| idx + 1),
+                ];
+                let excess = |n: usize| self.data[idx] - self.data[n] - talus;
+                let (mut steepest, mut total) = (0f32, 0f32);
+                for &n in neighbors.iter().flatten() {
+                    if excess(n) > 0. {
+                        steepest = steepest.max(excess(n));
+                        total += excess(n);
+                    }
+                }
+                if total == 0. {
+                    continue;
+                }
+                let amount = steepest / 2.;
+                change[idx] -= amount;
+                for &n in neighbors.iter().flatten() {
+                    if excess(n) > 0. {
+                        change[n] += amount * excess(n) / total;
+                    }
+                }
+            }
+            for (h, c) in self.data.iter_mut().zip(change.iter_mut()) {
+                *h += *c;
+                *c = 0.;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn erosion_conserves_material() {
+        // a slope down toward the last column
+        let (rows, cols) = (20, 20);
+        let data = (0..rows * cols).map(|idx| ((cols - idx % cols) as f32) * 0.5).collect();
+        let mut h = Heightmap { data, rows, cols };
+        let before: f32 = h.data.iter().sum();
+        let record = h.erode(&ErosionParams { iterations: 500, ..ErosionParams::new() });
+        let after: f32 = h.data.iter().sum();
+        let eroded: f32 = record.eroded.data.iter().sum();
+        let deposited: f32 = record.deposited.data.iter().sum();
+        assert!(eroded > 0.);
+        assert!((after - (before - eroded + deposited)).abs() < 1e-2);
+    }
+
+    #[test]
+    fn weathering_flattens_spikes() {
+        let (rows, cols) = (5, 5);
+        let mut data = vec![0.; rows * cols];
+        data[12] = 6.;
+        let mut h = Heightmap { data, rows, cols };
+        h.weather(1., 50);
+        let total: f32 = h.data.iter().sum();
+        assert!((total - 6.).abs() < 1e-4);
+        for idx in 0..rows * cols {
+            let (i, j) = (idx / cols, idx % cols);
+            if j + 1 < cols { assert!((h.data[idx] - h.data[idx + 1]).abs() <= 1.01) }
+            if i + 1 < rows { assert!((h.data[idx] - h.data[idx + cols]).abs() <= 1.01) }
+        }
+    }
+}
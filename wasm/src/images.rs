@@ -0,0 +1,207 @@
+use std::convert::TryFrom;
+
+use png::{BitDepth, ColorType, Decoder, Encoder, Transformations};
+
+use crate::{terrain::Heightmap, Canvas, Color};
+
+// colors of heights from the water level up to the highest point, evenly spaced
+const LAND_COLORS: [u32; 5] = [0x4c8c3c, 0x9cb45a, 0xd2be78, 0x96735a, 0xf4f4f4];
+// colors of depths from the water level down to the lowest point
+const WATER_COLORS: [u32; 2] = [0x78aadc, 0x1e3c78];
+// direction toward the light for hillshading, from the north west and 45 degrees up, with y pointing south
+const HILLSHADE_LIGHT: [f32; 3] = [-0.5, -0.5, std::f32::consts::FRAC_1_SQRT_2];
+
+// a PNG file holding an image of width by height pixels, with rows of samples from the top left
+// 16 bit samples are big-endian, as PNG stores them
+// fails for images with no pixels, which PNG can't hold, or data that doesn't match the size and format
+pub fn encode_png(data: &[u8], width: usize, height: usize, color: ColorType, depth: BitDepth) -> Result<Vec<u8>, String> {
+    if width == 0 || height == 0 {
+        return Err(format!("Can't save a {}x{} image as a PNG", width, height));
+    }
+    let (width, height) = u32::try_from(width).ok().zip(u32::try_from(height).ok())
+        .ok_or_else(|| format!("{}x{} is too large for a PNG", width, height))?;
+    let mut file = Vec::new();
+    let mut encoder = Encoder::new(&mut file, width, height);
+    encoder.set_color(color);
+    encoder.set_depth(depth);
+    let mut writer = encoder.write_header().map_err(|e| format!("Can't write PNG header: {}", e))?;
+    writer.write_image_data(data).map_err(|e| format!("Can't write PNG data: {}", e))?;
+    writer.finish().map_err(|e| format!("Can't finish PNG: {}", e))?;
+    Ok(file)
+}
+
+impl Canvas {
+    // the canvas as an 8 bit RGBA PNG file; pixels nothing was drawn in are clear
+    // fails for an empty canvas
+    pub fn to_png(&self) -> Result<Vec<u8>, String> {
+        encode_png(&self.data, self.cols, self.rows, ColorType::Rgba, BitDepth::Eight)
+    }
+}
+
+impl Heightmap {
+    // heights from a greyscale PNG file, one per pixel, with rows running down the image
+    // black is at offset and white at offset + vertical_scale; 16 bit images keep their extra precision
+    // any alpha channel is ignored; fails if vertical_scale or offset isn't finite
+    pub fn from_png(file: &[u8], vertical_scale: f32, offset: f32) -> Result<Self, String> {
+        if !vertical_scale.is_finite() || !offset.is_finite() {
+            return Err(format!("Invalid vertical scale {} or offset {}", vertical_scale, offset));
+        }
+        let mut decoder = Decoder::new(file);
+        // widen 1, 2 and 4 bit greys to 8 bits
+        decoder.set_transformations(Transformations::EXPAND);
+        let mut reader = decoder.read_info().map_err(|e| format!("Can't read PNG: {}", e))?;
+        let mut data = vec![0; reader.output_buffer_size()];
+        let info = reader.next_frame(&mut data).map_err(|e| format!("Can't read PNG: {}", e))?;
+        let channels = match info.color_type {
+            ColorType::Grayscale => 1,
+            ColorType::GrayscaleAlpha => 2,
+            other => return Err(format!("Heightmaps must be greyscale, not {:?}", other)),
+        };
+        let (rows, cols) = (info.height as usize, info.width as usize);
+        let bytes = match info.bit_depth {
+            BitDepth::Sixteen => 2,
+            _ => 1,
+        };
+        let max = if bytes == 2 { u16::MAX as f32 } else { u8::MAX as f32 };
+        let heights = data[..info.buffer_size()]
+            .chunks(bytes * channels)
+            .map(|pixel| {
+                let value = if bytes == 2 { u16::from_be_bytes([pixel[0], pixel[1]]) as f32 } else { pixel[0] as f32 };
+                offset + value / max * vertical_scale
+            })
+            .collect();
+        Ok(Heightmap { data: heights, rows, cols })
+    }
+}
+
+impl Heightmap {
+    // lowest and highest heights, ignoring NaN
+    pub fn range(&self) -> (f32, f32) {
+        self.data.iter().filter(|h| !h.is_nan())
+            .fold((f32::INFINITY, f32::NEG_INFINITY), |(low, high), h| (low.min(*h), high.max(*h)))
+    }
+
+    // a 16 bit greyscale PNG file, with offset as black and offset + vertical_scale as white
+    // heights outside that range are clamped; `from_png` with the same settings reads the heights back
+    // fails for an empty heightmap
+    pub fn to_png(&self, vertical_scale: f32, offset: f32) -> Result<Vec<u8>, String> {
+        let data: Vec<u8> = self.data.iter()
+            .flat_map(|h| {
+                let value = ((h - offset) / vertical_scale).clamp(0., 1.);
+                ((value * u16::MAX as f32).round() as u16).to_be_bytes()
+            })
+            .collect();
+        encode_png(&data, self.cols, self.rows, ColorType::Grayscale, BitDepth::Sixteen)
+    }
+
+    // an RGB PNG file colored by height, blue below water_level and green through to white above it,
+    // shaded as if lit from the north west (the top left) so slopes stand out
+    // fails for an empty heightmap
+    pub fn to_hillshade_png(&self, water_level: f32) -> Result<Vec<u8>, String> {
+        let (low, high) = self.range();
+        let at = |i: usize, j: usize| self.data[i * self.cols + j];
+        let mut data = Vec::with_capacity(self.rows * self.cols * 3);
+        for i in 0..self.rows {
+            for j in 0..self.cols {
+                let h = at(i, j);
+                // slopes from the neighbours on either side, or the one side at the edges
+                let (up, down) = (i.saturating_sub(1), (i + 1).min(self.rows - 1));
+                let (left, right) = (j.saturating_sub(1), (j + 1).min(self.cols - 1));
+                let dx = (at(i, right) - at(i, left)) / (right - left).max(1) as f32;
+                let dy = (at(down, j) - at(up, j)) / (down - up).max(1) as f32;
+                let [lx, ly, lz] = HILLSHADE_LIGHT;
+                let shade = ((lz - dx * lx - dy * ly) / (dx * dx + dy * dy + 1.).sqrt()).max(0.);
+                let color = if h.is_nan() {
+                    Color::from_hex(0)
+                } else if h < water_level {
+                    ramp(&WATER_COLORS, (water_level - h) / (water_level - low))
+                } else {
+                    ramp(&LAND_COLORS, (h - water_level) / (high - water_level))
+                };
+                let color = color.lit([0.35 + 0.65 * shade / lz; 3]);
+                data.extend([color.r, color.g, color.b]);
+            }
+        }
+        encode_png(&data, self.cols, self.rows, ColorType::Rgb, BitDepth::Eight)
+    }
+}
+
+// the color a fraction t of the way along evenly spaced colors, blending between the nearest two
+fn ramp(colors: &[u32], t: f32) -> Color {
+    let t = if t.is_finite() { t.clamp(0., 1.) } else { 0. } * (colors.len() - 1) as f32;
+    let i = (t.floor() as usize).min(colors.len() - 2);
+    let (a, b, w) = (Color::from_hex(colors[i]), Color::from_hex(colors[i + 1]), t - i as f32);
+    let mix = |a: u8, b: u8| (a as f32 + (b as f32 - a as f32) * w).round() as u8;
+    Color { r: mix(a.r, b.r), g: mix(a.g, b.g), b: mix(a.b, b.b) }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Color;
+
+    #[test]
+    fn canvas_round_trips_through_png() {
+        let mut canvas = Canvas::new(3, 5);
+        canvas.set_pixel(0, 0, &Color::from_hex(0xff8000));
+        canvas.set_pixel(2, 4, &Color::from_hex(0x123456));
+        let file = canvas.to_png().unwrap();
+        let mut reader = png::Decoder::new(file.as_slice()).read_info().unwrap();
+        let mut data = vec![0; reader.output_buffer_size()];
+        let info = reader.next_frame(&mut data).unwrap();
+        assert_eq!((info.width, info.height, info.color_type), (5, 3, ColorType::Rgba));
+        assert_eq!(data, canvas.data);
+    }
+
+    #[test]
+    fn greyscale_pngs_become_heightmaps() {
+        let file = encode_png(&[0, 51, 255, 102], 2, 2, ColorType::Grayscale, BitDepth::Eight).unwrap();
+        let h = Heightmap::from_png(&file, 10., -2.).unwrap();
+        assert_eq!((h.rows, h.cols), (2, 2));
+        assert_eq!(h.data, [-2., 0., 8., 2.]);
+        // 16 bit samples are big-endian
+        let file = encode_png(&[0xff, 0xff, 0x80, 0x00, 0x00, 0x01], 3, 1, ColorType::Grayscale, BitDepth::Sixteen).unwrap();
+        let h = Heightmap::from_png(&file, 1., 0.).unwrap();
+        assert_eq!((h.rows, h.cols), (1, 3));
+        assert_eq!(h.data[0], 1.);
+        assert!((h.data[1] - 0.5).abs() < 1e-4);
+        assert!(h.data[2] > 0. && h.data[2] < 1e-4);
+        // color images aren't heightmaps
+        let file = encode_png(&[0; 12], 2, 2, ColorType::Rgb, BitDepth::Eight).unwrap();
+        assert!(Heightmap::from_png(&file, 1., 0.).is_err());
+        assert!(Heightmap::from_png(b"not a png", 1., 0.).is_err());
+    }
+
+    #[test]
+    fn heightmaps_round_trip_through_png() {
+        let h = Heightmap { data: vec![-3., 0., 2.5, 7., 1.25, -1.], rows: 2, cols: 3 };
+        let (low, high) = h.range();
+        assert_eq!((low, high), (-3., 7.));
+        let back = Heightmap::from_png(&h.to_png(high - low, low).unwrap(), high - low, low).unwrap();
+        assert_eq!((back.rows, back.cols), (2, 3));
+        assert!(h.data.iter().zip(&back.data).all(|(a, b)| (a - b).abs() < 1e-3));
+    }
+
+    #[test]
+    fn hillshade_colors_water_and_slopes() {
+        // a ramp rising toward the east, with a pit of water in the middle
+        let data = (0..5 * 5).map(|idx| if idx == 12 { -5. } else { (idx % 5) as f32 }).collect();
+        let h = Heightmap { data, rows: 5, cols: 5 };
+        let file = h.to_hillshade_png(0.).unwrap();
+        let mut reader = png::Decoder::new(file.as_slice()).read_info().unwrap();
+        let mut rgb = vec![0; reader.output_buffer_size()];
+        let info = reader.next_frame(&mut rgb).unwrap();
+        assert_eq!((info.width, info.height, info.color_type), (5, 5, ColorType::Rgb));
+        let pixel = |i: usize, j: usize| &rgb[(i * 5 + j) * 3..][..3];
+        // water is bluer than it is red
+        assert!(pixel(2, 2)[2] > pixel(2, 2)[0]);
+        // the same ramp falling toward the east faces away from the north west light, so it's darker
+        // where it's just as high
+        let data = (0..5 * 5).map(|idx| (4 - idx % 5) as f32).collect();
+        let file = Heightmap { data, rows: 5, cols: 5 }.to_hillshade_png(0.).unwrap();
+        let mut reader = png::Decoder::new(file.as_slice()).read_info().unwrap();
+        let mut away = vec![0; reader.output_buffer_size()];
+        reader.next_frame(&mut away).unwrap();
+        assert!((0..3).all(|c| away[2 * 3 + c] < pixel(0, 2)[c]));
+    }
+}
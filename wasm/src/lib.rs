@@ -2,9 +2,25 @@ mod utils;
 mod triangles;
 mod scene;
 mod terrain;
+mod materials;
+mod biomes;
+mod water;
+mod erosion;
+mod lighting;
+mod textures;
+mod images;
+mod elevation;
 
-use scene::{Scene, Camera};
-use terrain::perlin_layers;
+use scene::{clamp_water_level, view_normal, Scene, Camera};
+use terrain::{perlin_layers, perlin_octaves, sum_octaves, Heightmap, NoiseLayers};
+use materials::Material;
+use textures::Atlas;
+use elevation::ElevationGrid;
+use biomes::Climate;
+pub use biomes::BiomeThresholds;
+pub use erosion::ErosionParams;
+pub use lighting::Light;
+pub use elevation::{ElevationParams, NoData};
 use wasm_bindgen::{prelude::*, Clamped};
 
 use crate::utils::set_panic_hook;
@@ -18,7 +34,7 @@ fn to_vertex(p: Pos2) -> Vertex {
 
 type Pos3 = [i32; 3];
 
-#[derive(Clone)]
+#[derive(Clone, PartialEq)]
 pub struct Color {
     pub r: u8,
     pub g: u8,
@@ -26,13 +42,33 @@ pub struct Color {
 }
 
 impl Color {
-    pub fn scaled(&self, c: f32) -> Color {
+    // with each channel multiplied by the matching one of [r, g, b]
+    pub fn lit(&self, [r, g, b]: [f32; 3]) -> Color {
         Color {
-            r: (((self.r as f32) * c) as u8).min(255).max(0),
-            g: (((self.g as f32) * c) as u8).min(255).max(0),
-            b: (((self.b as f32) * c) as u8).min(255).max(0),
+            r: ((self.r as f32) * r).clamp(0., 255.) as u8,
+            g: ((self.g as f32) * g).clamp(0., 255.) as u8,
+            b: ((self.b as f32) * b).clamp(0., 255.) as u8,
         }
     }
+
+    // from a color packed as 0xRRGGBB
+    pub fn from_hex(hex: u32) -> Color {
+        Color {
+            r: (hex >> 16) as u8,
+            g: (hex >> 8) as u8,
+            b: hex as u8,
+        }
+    }
+
+    // multiplied channel by channel with another color, as if each channel were from 0 to 1
+    pub fn modulated(&self, other: &Color) -> Color {
+        let channel = |a: u8, b: u8| ((a as u16 * b as u16 + 127) / 255) as u8;
+        Color { r: channel(self.r, other.r), g: channel(self.g, other.g), b: channel(self.b, other.b) }
+    }
+
+    pub fn to_hex(&self) -> u32 {
+        ((self.r as u32) << 16) | ((self.g as u32) << 8) | self.b as u32
+    }
 }
 
 pub struct Matrix<T: Copy> { 
@@ -62,8 +98,23 @@ impl<T: Copy> Matrix<T> {
     }
 }
 
+// how a color drawn over a canvas pixel combines with what's already there
+#[wasm_bindgen]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum BlendMode {
+    // covers what's behind in proportion to its alpha
+    #[default]
+    SourceOver,
+    // adds to what's behind, brightening it
+    Additive,
+    // multiplies what's behind, tinting and darkening it like colored glass
+    Multiply,
+}
+
 pub struct Canvas {
     pub data: Vec<u8>,
+    // optional per-pixel distance toward the viewer of whatever was drawn there; larger is closer
+    depth: Option<Vec<f32>>,
     rows: usize,
     cols: usize,
 }
@@ -72,11 +123,46 @@ impl Canvas {
     pub fn new(rows: usize, cols: usize) -> Canvas {
         Canvas {
             data: vec![0; rows * cols * 4],
+            depth: None,
             rows,
             cols,
         }
     }
 
+    pub fn with_depth(rows: usize, cols: usize) -> Canvas {
+        Canvas {
+            depth: Some(vec![f32::NEG_INFINITY; rows * cols]),
+            ..Canvas::new(rows, cols)
+        }
+    }
+
+    pub fn has_depth(&self) -> bool {
+        self.depth.is_some()
+    }
+
+    // checks whether something at depth d would be in front of what's already at (i, j)
+    // if it would and write is set, records d as the new depth there
+    // always passes for canvases without a depth channel
+    pub fn depth_test(&mut self, i: usize, j: usize, d: f32, write: bool) -> bool {
+        if i >= self.rows || j >= self.cols {
+            return false;
+        }
+        match self.depth.as_mut() {
+            Some(depth) => {
+                let idx = i * self.cols + j;
+                if d > depth[idx] {
+                    if write {
+                        depth[idx] = d;
+                    }
+                    true
+                } else {
+                    false
+                }
+            }
+            None => true,
+        }
+    }
+
     pub fn set_pixel(&mut self, i: usize, j: usize, c: &Color) {
         if i >= self.rows || j >= self.cols {
             return;
@@ -88,6 +174,71 @@ impl Canvas {
         self.data[i0 + 3] = 255;
     }
 
+    // draws c over the pixel at (i, j) with opacity alpha, letting what's already there show through
+    // where nothing has been drawn yet, every mode draws c as it is
+    pub fn blend_pixel(&mut self, i: usize, j: usize, c: &Color, alpha: f32, mode: BlendMode) {
+        if i >= self.rows || j >= self.cols {
+            return;
+        }
+        let i0 = (i * self.cols + j) * 4;
+        let dst_alpha = self.data[i0 + 3] as f32 / 255.;
+        let out_alpha = match mode {
+            BlendMode::Additive => (alpha + dst_alpha).min(1.),
+            _ => alpha + dst_alpha * (1. - alpha),
+        };
+        if out_alpha <= 0. {
+            return;
+        }
+        let mix = |src: u8, dst: u8| {
+            let (src, dst) = (src as f32, dst as f32);
+            let value = match mode {
+                BlendMode::SourceOver => (src * alpha + dst * dst_alpha * (1. - alpha)) / out_alpha,
+                BlendMode::Additive => (src * alpha + dst * dst_alpha) / out_alpha,
+                // the product shows only where there's something behind to multiply
+                BlendMode::Multiply => {
+                    let src = src * (1. - dst_alpha) + src * dst / 255. * dst_alpha;
+                    (src * alpha + dst * dst_alpha * (1. - alpha)) / out_alpha
+                }
+            };
+            value.round().clamp(0., 255.) as u8
+        };
+        self.data[i0] = mix(c.r, self.data[i0]);
+        self.data[i0 + 1] = mix(c.g, self.data[i0 + 1]);
+        self.data[i0 + 2] = mix(c.b, self.data[i0 + 2]);
+        self.data[i0 + 3] = (out_alpha * 255.).round() as u8;
+    }
+
+    // a canvas a factor smaller along each side, each pixel averaging a factor by factor block of this one
+    // colors are weighted by their alpha, so that edges blend with whatever the canvas is shown over
+    // depth isn't kept
+    pub fn downsampled(&self, factor: usize) -> Canvas {
+        let mut canvas = Canvas::new(self.rows / factor, self.cols / factor);
+        // sums are 64 bit so large factors can't overflow them
+        let samples = factor as u64 * factor as u64;
+        for i in 0..canvas.rows {
+            for j in 0..canvas.cols {
+                let mut sums = [0u64; 4];
+                for si in i * factor..(i + 1) * factor {
+                    for sj in j * factor..(j + 1) * factor {
+                        let pixel = &self.data[(si * self.cols + sj) * 4..][..4];
+                        let alpha = pixel[3] as u64;
+                        for c in 0..3 {
+                            sums[c] += pixel[c] as u64 * alpha;
+                        }
+                        sums[3] += alpha;
+                    }
+                }
+                let idx = (i * canvas.cols + j) * 4;
+                for c in 0..3 {
+                    // pixels with nothing drawn in them stay clear black
+                    canvas.data[idx + c] = (sums[c] + sums[3] / 2).checked_div(sums[3]).unwrap_or(0) as u8;
+                }
+                canvas.data[idx + 3] = ((sums[3] + samples / 2) / samples) as u8;
+            }
+        }
+        canvas
+    }
+
     pub fn row(&self, i: usize) -> &[u8] {
         &self.data[4 * self.cols * i..4 * self.cols * (i + 1)]
     }
@@ -101,50 +252,361 @@ impl Canvas {
     }
 }
 
+// the visible faces of a block, as they appear on screen
+#[wasm_bindgen]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Face {
+    Top,
+    Left,
+    Right,
+}
+
+// a block and the face of it under some point on the screen
+#[wasm_bindgen]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Pick {
+    pub x: i32,
+    pub y: i32,
+    pub z: i32,
+    pub face: Face,
+    // offset to the block that touches the picked face, which depends on the face and the camera's rotation
+    pub nx: i32,
+    pub ny: i32,
+    pub nz: i32,
+}
+
+// properties of a material other than its name and color
+#[wasm_bindgen]
+#[derive(Clone, Copy, Debug)]
+pub struct MaterialFlags {
+    pub transparent: bool,
+    pub emissive: bool,
+    pub liquid: bool,
+}
+
 #[wasm_bindgen]
 pub struct StateManager {
     scene: Scene,
     camera: Camera,
     canvas: Canvas,
+    // set when the camera's scale or rotation changed since the last draw, so the canvas can't just be shifted
+    stale: bool,
 }
 
 #[wasm_bindgen]
 impl StateManager {
+    // a world generated from noise and eroded; water_level is clamped to -1021 through 1023 here and in the other
+    // constructors, so the water and the ground under it stay within the blocks a world can hold
+    #[allow(clippy::too_many_arguments)]
     pub fn new(
         height: usize, width: usize, perlin_periods: Vec<usize>, perlin_amplitudes: Vec<f32>,
-        pixel_height: usize, pixel_width: usize, scale: f32,
+        pixel_height: usize, pixel_width: usize, scale: f32, seed: u64, water_level: i32, biomes: &BiomeThresholds,
+        erosion: &ErosionParams,
     ) -> Self {
         set_panic_hook();
+        let water_level = clamp_water_level(water_level);
         let max_amp = perlin_amplitudes.clone().into_iter().reduce(|acc, x| acc.max(x)).unwrap();
-        let heightmap = perlin_layers(height, width, perlin_periods, perlin_amplitudes);
-        let scene = Scene::from_heightmap(heightmap, -(max_amp as i32));
+        let mut heightmap = perlin_layers(height, width, perlin_periods, perlin_amplitudes, seed);
+        let sediment = heightmap.erode(erosion);
+        heightmap.weather(erosion.talus, erosion.thermal_iterations);
+        // erosion can dig below the lowest height the noise reaches, and the world should still have a flat bottom
+        let lowest = heightmap.data.iter().fold(f32::INFINITY, |acc, h| acc.min(*h));
+        let min_height = (-(max_amp as i32)).min(lowest as i32);
+        let climate = Climate::generate(height, width, seed);
+        let scene = Scene::from_heightmap(
+            heightmap, min_height, water_level, &climate, biomes, Some(&sediment),
+        );
         let camera = Camera::new([0, 0], pixel_height, pixel_width, scale);
         let canvas = Canvas::new(pixel_height, pixel_width);
         Self {
-            scene, camera, canvas
+            scene, camera, canvas, stale: false,
+        }
+    }
+
+    // a world built from a greyscale PNG heightmap, e.g. real elevation data or a hand-painted map
+    // each pixel is a column; black is at offset and white at offset + vertical_scale blocks
+    // the seed only picks the climate that decides biomes
+    // fails if the file isn't a greyscale PNG, or its heights are taller than a world can hold
+    #[allow(clippy::too_many_arguments)]
+    pub fn from_heightmap_png(
+        file: &[u8], vertical_scale: f32, offset: f32, pixel_height: usize, pixel_width: usize, scale: f32, seed: u64,
+        water_level: i32, biomes: &BiomeThresholds,
+    ) -> Result<StateManager, String> {
+        set_panic_hook();
+        let water_level = clamp_water_level(water_level);
+        let heightmap = Heightmap::from_png(file, vertical_scale, offset)?;
+        Self::from_heights(heightmap, pixel_height, pixel_width, scale, seed, water_level, biomes)
+            .map_err(|e| format!("{}; lower the vertical scale or move the offset", e))
+    }
+
+    // a world built from an ESRI ASCII grid (.asc) elevation model, resampled and scaled as set in params
+    // each cell becomes a block as wide as the cell; the seed only picks the climate that decides biomes
+    // fails if the grid can't be parsed or its heights, in blocks, are taller than a world can hold
+    #[allow(clippy::too_many_arguments)]
+    pub fn from_ascii_grid(
+        text: &str, params: &ElevationParams, pixel_height: usize, pixel_width: usize, scale: f32, seed: u64,
+        water_level: i32, biomes: &BiomeThresholds,
+    ) -> Result<StateManager, String> {
+        set_panic_hook();
+        let water_level = clamp_water_level(water_level);
+        let mut grid = ElevationGrid::parse_asc(text)?;
+        if params.cell_width.is_finite() && params.cell_width > 0. {
+            grid.cellsize = params.cell_width;
+        }
+        if params.rows > 0 || params.cols > 0 {
+            let rows = if params.rows > 0 { params.rows } else { grid.rows };
+            let cols = if params.cols > 0 { params.cols } else { grid.cols };
+            grid = grid.resampled(rows, cols);
+        }
+        let mut heightmap = grid.to_heightmap(params.exaggeration, params.base);
+        if params.no_data == NoData::Water {
+            // low enough to be under at least a block of water
+            heightmap.data.iter_mut().filter(|h| h.is_nan()).for_each(|h| *h = (water_level - 1) as f32);
+        }
+        Self::from_heights(heightmap, pixel_height, pixel_width, scale, seed, water_level, biomes).map_err(|e| format!(
+            "{}; lower the exaggeration, or set the cell width if the grid's cells aren't in the same units as its \
+            elevations", e,
+        ))
+    }
+
+    // a world without edges, generated chunk by chunk as the camera moves around it
+    // at most chunk_budget chunks are kept in memory at once
+    #[allow(clippy::too_many_arguments)]
+    pub fn new_infinite(
+        perlin_periods: Vec<usize>, perlin_amplitudes: Vec<f32>, seed: u64, chunk_budget: usize,
+        pixel_height: usize, pixel_width: usize, scale: f32, water_level: i32, biomes: &BiomeThresholds,
+    ) -> Self {
+        set_panic_hook();
+        let water_level = clamp_water_level(water_level);
+        let noise = NoiseLayers::new(perlin_periods, perlin_amplitudes, seed);
+        let min_height = -(noise.max_amplitude() as i32);
+        let scene = Scene::generated(noise, seed, *biomes, min_height, water_level, chunk_budget);
+        let camera = Camera::new([0, 0], pixel_height, pixel_width, scale);
+        let canvas = Canvas::new(pixel_height, pixel_width);
+        Self {
+            scene, camera, canvas, stale: false,
         }
     }
 
     pub fn draw(&mut self) {
         self.canvas = self.scene.draw(&self.camera);
+        self.stale = false;
     }
 
     pub fn get_canvas(&self) -> Clamped<Vec<u8>> {
         Clamped(self.canvas.data.clone())
     }
 
+    // the canvas as a PNG file, e.g. for saving a screenshot
+    // fails if the canvas has no pixels
+    pub fn get_png(&self) -> Result<Vec<u8>, String> {
+        self.canvas.to_png()
+    }
+
+    // the canvas shrunk by a factor along each side as a PNG file, with each pixel averaging those it covers
+    // factor is clamped to 1 through the canvas' shorter side, so the thumbnail is never empty
+    // fails if the canvas has no pixels
+    pub fn get_thumbnail_png(&self, factor: usize) -> Result<Vec<u8>, String> {
+        let factor = factor.clamp(1, self.canvas.rows.min(self.canvas.cols).max(1));
+        self.canvas.downsampled(factor).to_png()
+    }
+
+    // the ground of every column as the world is now, after erosion and edits, as a 16 bit greyscale PNG file
+    // offset is black and offset + vertical_scale white, so `from_heightmap_png` with the same settings reads it back
+    // liquids are left out, and columns with no ground are black
+    // fails for infinite worlds, which have no edges
+    pub fn get_heightmap_png(&self, vertical_scale: f32, offset: f32) -> Result<Vec<u8>, String> {
+        self.surface()?.to_png(vertical_scale, offset)
+    }
+
+    // the ground of every column as the world is now, colored by height and hillshaded, as a PNG file
+    // fails for infinite worlds, which have no edges
+    pub fn get_hillshade_png(&self, water_level: f32) -> Result<Vec<u8>, String> {
+        self.surface()?.to_hillshade_png(water_level)
+    }
+
+    // places a block of the given material, replacing any block already at (x, y, z)
+    // returns whether the block was placed, which fails for unknown materials and for z outside -1024 to 1023
+    // changes show up on the next draw
+    pub fn place_block(&mut self, x: i32, y: i32, z: i32, material: u8) -> bool {
+        self.scene.place([x, y, z], material)
+    }
+
+    // removes the block at (x, y, z), returning whether there was one
+    pub fn remove_block(&mut self, x: i32, y: i32, z: i32) -> bool {
+        self.scene.remove([x, y, z])
+    }
+
+    // material id of the block at (x, y, z), if there is one
+    pub fn get_block(&self, x: i32, y: i32, z: i32) -> Option<u8> {
+        self.scene.material_at([x, y, z])
+    }
+
+    pub fn material_id(&self, name: &str) -> Option<u8> {
+        self.scene.materials().id(name)
+    }
+
+    pub fn material_name(&self, id: u8) -> Option<String> {
+        self.scene.materials().get(id).map(|m| m.name.clone())
+    }
+
+    pub fn material_flags(&self, id: u8) -> Option<MaterialFlags> {
+        self.scene.materials().get(id).map(
+            |m| MaterialFlags { transparent: m.transparent, emissive: m.emissive, liquid: m.liquid }
+        )
+    }
+
+    // sets how opaque a material is, from 0 (invisible) to 1 (solid), returning false for unknown materials
+    // e.g. lower the opacity of water to see the ground beneath it
+    pub fn set_material_alpha(&mut self, id: u8, alpha: f32) -> bool {
+        match self.scene.materials_mut().get_mut(id) {
+            Some(m) => {
+                m.alpha = alpha.clamp(0., 1.);
+                true
+            }
+            None => false,
+        }
+    }
+
+    // sets how a material combines with whatever is drawn behind it, returning false for unknown materials
+    // materials that don't cover what's behind them are drawn after opaque ones
+    pub fn set_material_blend(&mut self, id: u8, mode: BlendMode) -> bool {
+        match self.scene.materials_mut().get_mut(id) {
+            Some(m) => {
+                m.blend = mode;
+                true
+            }
+            None => false,
+        }
+    }
+
+    // adds a material with color packed as 0xRRGGBB and top/left/right face brightness, returning its id
+    // face brightness multiplies the scene's light on those faces; 1 leaves the light as it is
+    // returns nothing if the material table is full or already has a material with that name
+    #[allow(clippy::too_many_arguments)]
+    pub fn add_material(
+        &mut self, name: &str, color: u32, top_shading: f32, left_shading: f32, right_shading: f32,
+        transparent: bool, emissive: bool, liquid: bool,
+    ) -> Option<u8> {
+        self.scene.materials_mut().add(Material {
+            shading: [top_shading, left_shading, right_shading],
+            transparent, emissive, liquid,
+            ..Material::solid(name, Color::from_hex(color))
+        })
+    }
+
+    // sets the atlas textured materials draw their tiles from, from RGBA pixels in rows from the top left
+    // the atlas is split into square tiles of tile_size pixels, numbered along each row
+    // returns false, leaving the current atlas, if data doesn't hold width * height pixels or no tile fits
+    pub fn set_texture_atlas(&mut self, data: Vec<u8>, width: usize, height: usize, tile_size: usize) -> bool {
+        match Atlas::new(data, width, height, tile_size) {
+            Some(atlas) => {
+                self.scene.materials_mut().set_atlas(Some(atlas));
+                true
+            }
+            None => false,
+        }
+    }
+
+    // goes back to drawing every material with its flat color
+    pub fn clear_texture_atlas(&mut self) {
+        self.scene.materials_mut().set_atlas(None);
+    }
+
+    // draws a material's top, left and right faces with tiles of the atlas
+    // faces whose tile isn't in the atlas keep the flat color
+    pub fn set_material_tiles(&mut self, id: u8, top: u32, left: u32, right: u32) -> bool {
+        match self.scene.materials_mut().get_mut(id) {
+            Some(m) => {
+                m.tiles = Some([top, left, right]);
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub fn clear_material_tiles(&mut self, id: u8) -> bool {
+        match self.scene.materials_mut().get_mut(id) {
+            Some(m) => {
+                m.tiles = None;
+                true
+            }
+            None => false,
+        }
+    }
+
+    // columns along y in the heightmap, noise or elevation model the world was built from; 0 for infinite worlds
+    pub fn world_rows(&self) -> usize {
+        self.scene.size().map_or(0, |[rows, _]| rows)
+    }
+
+    // columns along x in the heightmap, noise or elevation model the world was built from; 0 for infinite worlds
+    pub fn world_cols(&self) -> usize {
+        self.scene.size().map_or(0, |[_, cols]| cols)
+    }
+
+    // z of the highest block at (x, y), if there are any
+    pub fn column_height(&self, x: i32, y: i32) -> Option<i32> {
+        self.scene.column_height(x, y)
+    }
+
+    // the block and face drawn at canvas pixel (px, py), if any
+    pub fn pick(&self, px: usize, py: usize) -> Option<Pick> {
+        self.scene.pick(&self.camera, px, py).map(
+            |([x, y, z], face)| {
+                let [nx, ny, nz] = self.camera.rotation.inverse().turn_offset(view_normal(face));
+                Pick { x, y, z, face, nx, ny, nz }
+            }
+        )
+    }
+
+    // lighting used from the next draw on
+    pub fn set_light(&mut self, light: &Light) {
+        self.scene.set_light(*light);
+    }
+
+    pub fn light(&self) -> Light {
+        self.scene.light()
+    }
+
+    // switch between per-pixel depth testing and the faster slice-based visibility
+    pub fn set_depth_buffer(&mut self, enabled: bool) {
+        self.scene.depth_buffer = enabled;
+    }
+
+    // darken corners of faces that are crowded by other blocks; on by default
+    pub fn set_ambient_occlusion(&mut self, enabled: bool) {
+        self.scene.ambient_occlusion = enabled;
+    }
+
+    // smooths edges by drawing factor by factor pixels for each pixel of the canvas and averaging them
+    // factor is clamped to 1, which turns it off, through 4
+    // returns the pixel multiplier, factor squared: the number of pixels drawn per canvas pixel
+    // it's not a measured cost, but draw time grows roughly in step with it
+    pub fn set_antialiasing(&mut self, factor: u32) -> u32 {
+        let factor = factor.clamp(1, 4);
+        self.scene.supersampling = factor as usize;
+        self.stale = true;
+        factor * factor
+    }
+
     pub fn shift_y(&mut self, dy: i32) {
         let new_origin = [self.camera.origin[0], self.camera.origin[1] + dy];
+        if self.stale {
+            self.camera.origin = new_origin;
+            self.draw();
+            return;
+        }
         if dy <= 0 {
-            let temp_camera = Camera::new(new_origin, dy.abs() as usize, self.camera.width, self.camera.scale);
+            let temp_camera = self.camera.region(new_origin, dy.unsigned_abs() as usize, self.camera.width);
             let canvas_slice = self.scene.draw(&temp_camera);
             let shift_size = canvas_slice.size();
             self.canvas.data.rotate_right(shift_size);
             self.canvas.data[..shift_size].copy_from_slice(&canvas_slice.data);
         } else {
-            let temp_camera = Camera::new(
+            let temp_camera = self.camera.region(
                 [new_origin[0], self.camera.origin[1] + self.camera.height as i32],
-                dy as usize, self.camera.width, self.camera.scale
+                dy as usize, self.camera.width,
             );
             let canvas_slice = self.scene.draw(&temp_camera);
             let shift_size = canvas_slice.size();
@@ -157,8 +619,13 @@ impl StateManager {
 
     pub fn shift_x(&mut self, dx: i32) {
         let new_origin = [self.camera.origin[0] + dx, self.camera.origin[1]];
+        if self.stale {
+            self.camera.origin = new_origin;
+            self.draw();
+            return;
+        }
         if dx <= 0 {
-            let temp_camera = Camera::new(new_origin, self.camera.height, dx.abs() as usize, self.camera.scale);
+            let temp_camera = self.camera.region(new_origin, self.camera.height, dx.unsigned_abs() as usize);
             let canvas_slice = self.scene.draw(&temp_camera);
             let line_shift = canvas_slice.cols * 4;
             for i in 0..self.canvas.rows {
@@ -167,9 +634,9 @@ impl StateManager {
                 row[..line_shift].copy_from_slice(canvas_slice.row(i));
             }
         } else {
-            let temp_camera = Camera::new(
+            let temp_camera = self.camera.region(
                 [self.camera.origin[0] + self.camera.width as i32, new_origin[1]],
-                self.camera.height, dx as usize, self.camera.scale,
+                self.camera.height, dx as usize,
             );
             let canvas_slice = self.scene.draw(&temp_camera);
             let line_shift = canvas_slice.cols * 4;
@@ -183,7 +650,111 @@ impl StateManager {
         self.camera.origin = new_origin;
     }
 
+    // moves the view so that screen space point (x, y) is at the top left of the canvas
+    // changes show up on the next draw
+    pub fn set_origin(&mut self, x: i32, y: i32) {
+        self.camera.origin = [x, y];
+        self.camera.zoom_remainder = [0., 0.];
+        self.stale = true;
+    }
+
     pub fn shift(&mut self, dx: i32, dy: i32) {
         self.camera.origin = [self.camera.origin[0] + dx, self.camera.origin[1] + dy];
     }
+
+    // moves the view so that world point (x, y) at z = 0 is at the center of the canvas
+    // changes show up on the next draw
+    pub fn look_at(&mut self, x: f32, y: f32) {
+        self.camera.look_at([x, y]);
+        self.stale = true;
+    }
+
+    // turns the view a quarter turn around the world point at the center of the screen
+    // clockwise turns the scene clockwise on screen; changes show up on the next draw
+    pub fn rotate(&mut self, clockwise: bool) {
+        self.camera.rotate(clockwise);
+        self.stale = true;
+    }
+
+    // multiplies the scale by factor, keeping the world point drawn at canvas position (px, py) where it is
+    // the scale is kept within 2 to 64, and factors that aren't finite and positive are ignored
+    // returns the new scale. changes show up on the next draw
+    pub fn zoom(&mut self, factor: f32, px: f32, py: f32) -> f32 {
+        self.camera.zoom(factor, px, py);
+        self.stale = true;
+        self.camera.scale
+    }
+}
+
+impl StateManager {
+    // the ground height of every column, for exporting
+    fn surface(&self) -> Result<Heightmap, String> {
+        self.scene.surface().ok_or_else(|| "Infinite worlds have no edges to export".to_string())
+    }
+
+    // a world of columns as high as the heightmap, leaving out those that are NaN
+    // fails if the heights are taller than a world can hold
+    fn from_heights(
+        heightmap: Heightmap, pixel_height: usize, pixel_width: usize, scale: f32, seed: u64, water_level: i32,
+        biomes: &BiomeThresholds,
+    ) -> Result<Self, String> {
+        heightmap.check_within_world()?;
+        // a couple of blocks below the lowest surface, so every column has some depth
+        let lowest = heightmap.data.iter().fold(f32::INFINITY, |acc, h| acc.min(*h));
+        let min_height = if lowest.is_finite() { lowest.floor() as i32 - 2 } else { 0 };
+        let climate = Climate::generate(heightmap.rows, heightmap.cols, seed);
+        let scene = Scene::from_heightmap(heightmap, min_height, water_level, &climate, biomes, None);
+        let camera = Camera::new([0, 0], pixel_height, pixel_width, scale);
+        let canvas = Canvas::new(pixel_height, pixel_width);
+        Ok(Self {
+            scene, camera, canvas, stale: false,
+        })
+    }
+}
+
+// the noise `StateManager::new` starts from, before erosion, or only the given octave of it
+// along with how far from 0 its heights can reach, which is the sum of the amplitudes it's made of
+fn noise_heightmap(
+    height: usize, width: usize, periods: Vec<usize>, amplitudes: Vec<f32>, seed: u64, octave: Option<usize>,
+) -> Result<(Heightmap, f32), String> {
+    if periods.len() != amplitudes.len() || periods.is_empty() {
+        return Err("Expected as many periods as amplitudes, and at least one of each".to_string());
+    }
+    if periods.contains(&0) {
+        return Err("Periods must be at least 1".to_string());
+    }
+    if height < 2 || width < 2 {
+        return Err(format!("A {}x{} world is too small for noise, which needs at least 2x2", width, height));
+    }
+    let mut octaves = perlin_octaves(height, width, periods, amplitudes.clone(), seed);
+    match octave {
+        Some(i) if i >= octaves.len() => Err(format!("No octave {}, there are {}", i, octaves.len())),
+        Some(i) => Ok((octaves.swap_remove(i), amplitudes[i].abs())),
+        None => Ok((
+            sum_octaves(octaves),
+            amplitudes.iter().map(|a| a.abs()).sum(),
+        )),
+    }
+}
+
+// a world's noise, or one octave of it, as a 16 bit greyscale PNG file for editing in an image editor
+// black is at minus and white at plus the sum of the amplitudes (or the octave's amplitude), so passing twice
+// that sum as vertical_scale and minus it as offset to `StateManager::from_heightmap_png` reads it back
+#[wasm_bindgen]
+pub fn heightmap_png(
+    height: usize, width: usize, perlin_periods: Vec<usize>, perlin_amplitudes: Vec<f32>, seed: u64,
+    octave: Option<usize>,
+) -> Result<Vec<u8>, String> {
+    let (heightmap, reach) = noise_heightmap(height, width, perlin_periods, perlin_amplitudes, seed, octave)?;
+    heightmap.to_png(2. * reach, -reach)
+}
+
+// a world's noise, or one octave of it, as a colored and hillshaded PNG file for seeing its shape at a glance
+#[wasm_bindgen]
+pub fn hillshade_png(
+    height: usize, width: usize, perlin_periods: Vec<usize>, perlin_amplitudes: Vec<f32>, seed: u64,
+    water_level: f32, octave: Option<usize>,
+) -> Result<Vec<u8>, String> {
+    let (heightmap, _) = noise_heightmap(height, width, perlin_periods, perlin_amplitudes, seed, octave)?;
+    heightmap.to_hillshade_png(water_level)
 }
@@ -0,0 +1,68 @@
+use wasm_bindgen::prelude::*;
+
+use crate::{Color, Pos3};
+
+// a sun shining on the whole scene from one direction, plus light that reaches every face equally
+#[wasm_bindgen]
+#[derive(Clone, Copy, Debug)]
+pub struct Light {
+    // direction toward the sun in world coordinates, with z up; doesn't need to be normalised
+    pub sun_x: f32,
+    pub sun_y: f32,
+    pub sun_z: f32,
+    // packed as 0xRRGGBB
+    pub sun_color: u32,
+    // brightness of faces facing away from the sun, from 0 to 1
+    pub ambient: f32,
+}
+
+#[wasm_bindgen]
+impl Light {
+    pub fn new() -> Self {
+        // high in the sky toward +x and +y, lighting the faces drawn on the left a little more than those on the right
+        Self {
+            sun_x: 3.,
+            sun_y: 4.,
+            sun_z: 5.,
+            sun_color: 0xe6e6e6,
+            ambient: 0.4,
+        }
+    }
+}
+
+impl Default for Light {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Light {
+    // red, green and blue multipliers for a face with the given outward normal
+    pub fn brightness(&self, normal: Pos3) -> [f32; 3] {
+        let len = (self.sun_x * self.sun_x + self.sun_y * self.sun_y + self.sun_z * self.sun_z).sqrt();
+        let facing = if len > 0. {
+            let [nx, ny, nz] = normal;
+            ((nx as f32 * self.sun_x + ny as f32 * self.sun_y + nz as f32 * self.sun_z) / len).max(0.)
+        } else {
+            0.
+        };
+        let sun = Color::from_hex(self.sun_color);
+        [sun.r, sun.g, sun.b].map(|c| self.ambient + facing * c as f32 / 255.)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn faces_toward_the_sun_are_brighter() {
+        let light = Light { sun_x: 1., sun_y: 0., sun_z: 1., sun_color: 0xffffff, ambient: 0.2 };
+        let top = light.brightness([0, 0, 1]);
+        let lit = light.brightness([1, 0, 0]);
+        let unlit = light.brightness([-1, 0, 0]);
+        assert!((top[0] - lit[0]).abs() < 1e-6);
+        assert!(lit[0] > unlit[0]);
+        assert_eq!(unlit, [0.2; 3]);
+    }
+}
@@ -0,0 +1,136 @@
+use std::convert::TryFrom;
+
+use crate::{textures::Atlas, BlendMode, Color};
+
+// blocks refer to materials by their index in a `Materials` table
+pub type MaterialId = u8;
+
+// ids of the materials in the default table
+pub const AIR: MaterialId = 0;
+pub const GRASS: MaterialId = 1;
+pub const DIRT: MaterialId = 2;
+pub const STONE: MaterialId = 3;
+pub const SAND: MaterialId = 4;
+pub const SNOW: MaterialId = 5;
+pub const WATER: MaterialId = 6;
+pub const FOREST: MaterialId = 7;
+pub const RED_SAND: MaterialId = 8;
+pub const TUNDRA: MaterialId = 9;
+pub const SILT: MaterialId = 10;
+
+pub struct Material {
+    pub name: String,
+    pub color: Color,
+    // brightness multipliers for the top, left and right faces, on top of the scene's light
+    pub shading: [f32; 3],
+    // whether blocks behind this material can be seen through it
+    pub transparent: bool,
+    // opacity when drawn over whatever is behind it, from 0 to 1
+    pub alpha: f32,
+    pub blend: BlendMode,
+    // whether this material gives off its own light
+    pub emissive: bool,
+    pub liquid: bool,
+    // atlas tiles drawn on the top, left and right faces in place of the flat color
+    pub tiles: Option<[u32; 3]>,
+}
+
+impl Material {
+    // an opaque, solid material, shaded only by the scene's light
+    pub fn solid(name: &str, color: Color) -> Self {
+        Material {
+            name: name.to_string(),
+            color,
+            shading: [1.; 3],
+            transparent: false,
+            alpha: 1.,
+            blend: BlendMode::SourceOver,
+            emissive: false,
+            liquid: false,
+            tiles: None,
+        }
+    }
+
+    // whether what's behind the material shows through it, so it's drawn after opaque materials
+    pub fn translucent(&self) -> bool {
+        self.alpha < 1. || self.blend != BlendMode::SourceOver
+    }
+}
+
+pub struct Materials {
+    list: Vec<Material>,
+    // textures for materials with tiles; materials are drawn with flat colors without one
+    atlas: Option<Atlas>,
+}
+
+impl Materials {
+    pub fn get(&self, id: MaterialId) -> Option<&Material> {
+        self.list.get(id as usize)
+    }
+
+    pub fn get_mut(&mut self, id: MaterialId) -> Option<&mut Material> {
+        self.list.get_mut(id as usize)
+    }
+
+    pub fn id(&self, name: &str) -> Option<MaterialId> {
+        self.list.iter().position(|m| m.name == name).map(|i| i as MaterialId)
+    }
+
+    // adds a material to the table, returning its id
+    // returns None if the table is full or already has a material with the same name, since names look materials up
+    pub fn add(&mut self, material: Material) -> Option<MaterialId> {
+        if self.id(&material.name).is_some() {
+            return None;
+        }
+        let id = MaterialId::try_from(self.list.len()).ok()?;
+        self.list.push(material);
+        Some(id)
+    }
+
+    pub fn atlas(&self) -> Option<&Atlas> {
+        self.atlas.as_ref()
+    }
+
+    pub fn set_atlas(&mut self, atlas: Option<Atlas>) {
+        self.atlas = atlas;
+    }
+}
+
+impl Default for Materials {
+    fn default() -> Self {
+        // order must match the id constants above
+        let list = vec![
+            Material {
+                transparent: true,
+                ..Material::solid("air", Color::from_hex(0x000000))
+            },
+            Material::solid("grass", Color::from_hex(0x5d9b3a)),
+            Material::solid("dirt", Color::from_hex(0x8a6240)),
+            Material::solid("stone", Color::from_hex(0x8c8c8c)),
+            Material::solid("sand", Color::from_hex(0xdccb8c)),
+            Material::solid("snow", Color::from_hex(0xf4f6fa)),
+            Material {
+                transparent: true,
+                alpha: 0.6,
+                liquid: true,
+                ..Material::solid("water", Color::from_hex(0x3a6fb4))
+            },
+            Material::solid("forest", Color::from_hex(0x2f6b2a)),
+            Material::solid("red sand", Color::from_hex(0xd08c4f)),
+            Material::solid("tundra", Color::from_hex(0x9aa58a)),
+            Material::solid("silt", Color::from_hex(0x7d7462)),
+            // tints whatever is behind it
+            Material {
+                transparent: true,
+                blend: BlendMode::Multiply,
+                ..Material::solid("glass", Color::from_hex(0xd8ecf4))
+            },
+            Material {
+                transparent: true,
+                alpha: 0.75,
+                ..Material::solid("ice", Color::from_hex(0xb4dcf0))
+            },
+        ];
+        Materials { list, atlas: None }
+    }
+}
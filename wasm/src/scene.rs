@@ -1,13 +1,36 @@
-use std::{collections::HashMap, hash::Hash};
+use std::{collections::{HashMap, HashSet}, hash::Hash};
 
-use crate::{Vertex, Canvas, Color, triangles::Triangle, Pos2, Pos3, terrain::Heightmap, utils::{round_down, round_up}, to_vertex};
+use crate::{
+    Vertex, Canvas, Color, Face, triangles::Triangle, Pos2, Pos3, terrain::{Heightmap, NoiseLayers}, utils::{round_down, round_up}, to_vertex,
+    materials::{Material, MaterialId, Materials, AIR, SILT, STONE, WATER},
+    erosion::ErosionRecord,
+    lighting::Light,
+    water::water_levels,
+    biomes::{classify, Biome, BiomeThresholds, Climate},
+};
 
 const THETA: f32 = std::f32::consts::FRAC_PI_6;
 const CHUNK_SIZE: i32 = 16;
+// brightness at a face's corner with 0 to 3 blocks around it, in front of the face
+const OCCLUSION_LEVELS: [f32; 4] = [1., 0.82, 0.68, 0.55];
+// furthest a column's shadow can reach, in blocks, when the sun is low
+const MAX_SHADOW_LENGTH: i32 = 64;
+// limits on pixels per block edge when zooming
+const MIN_SCALE: f32 = 2.;
+const MAX_SCALE: f32 = 64.;
+// lowest and highest z a block can be at; columns are stored densely, so this keeps each to a few kilobytes
+pub const WORLD_BOTTOM: i32 = -1024;
+pub const WORLD_TOP: i32 = 1023;
 
+// a water level within the blocks a world can hold, leaving a couple of blocks of ground under the lowest water
+pub fn clamp_water_level(water_level: i32) -> i32 {
+    water_level.clamp(WORLD_BOTTOM + 3, WORLD_TOP)
+}
+
+#[derive(Clone, Copy)]
 struct Block {
     pub origin: Pos3,
-    pub color: Color,
+    pub material: MaterialId,
 }
 
 impl Block {
@@ -36,8 +59,16 @@ struct Bounds {
 }
 
 impl Bounds {
-    pub fn contains(&self, block: &Block) -> bool {
-        let [x, y, _] = block.origin;
+    // bounds of the chunk whose hash map key is `key`
+    pub fn of_chunk(key: Pos2) -> Self {
+        Self {
+            x: (key[0], key[0] + CHUNK_SIZE),
+            y: (key[1], key[1] + CHUNK_SIZE),
+        }
+    }
+
+    pub fn contains(&self, pos: Pos3) -> bool {
+        let [x, y, _] = pos;
         x >= self.x.0
             && y >= self.y.0
             && x < self.x.1
@@ -45,30 +76,156 @@ impl Bounds {
     }
 }
 
+// the blocks stacked at one (x, y), stored densely from the lowest one up, with AIR filling any gaps
+// this keeps the cost of a block down to the size of its material id
+#[derive(Default)]
+struct Column {
+    base: i32,
+    cells: Vec<MaterialId>,
+}
+
+impl Column {
+    pub fn get(&self, z: i32) -> Option<MaterialId> {
+        if z < self.base {
+            return None;
+        }
+        match self.cells.get((z - self.base) as usize) {
+            Some(&m) if m != AIR => Some(m),
+            _ => None,
+        }
+    }
+
+    pub fn set(&mut self, z: i32, material: MaterialId) {
+        if self.cells.is_empty() {
+            self.base = z;
+        } else if z < self.base {
+            let mut cells = vec![AIR; (self.base - z) as usize];
+            cells.append(&mut self.cells);
+            self.cells = cells;
+            self.base = z;
+        }
+        let i = (z - self.base) as usize;
+        if i >= self.cells.len() {
+            self.cells.resize(i + 1, AIR);
+        }
+        self.cells[i] = material;
+    }
+
+    pub fn remove(&mut self, z: i32) -> Option<MaterialId> {
+        let material = self.get(z)?;
+        self.cells[(z - self.base) as usize] = AIR;
+        // trim air from both ends, so the top cell is always a block
+        while self.cells.last() == Some(&AIR) {
+            self.cells.pop();
+        }
+        let leading = self.cells.iter().take_while(|m| **m == AIR).count();
+        self.cells.drain(..leading);
+        self.base += leading as i32;
+        Some(material)
+    }
+
+    // z of the highest block
+    pub fn top(&self) -> Option<i32> {
+        if self.cells.is_empty() {
+            None
+        } else {
+            Some(self.base + self.cells.len() as i32 - 1)
+        }
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (i32, MaterialId)> + '_ {
+        self.cells.iter().enumerate()
+            .filter(|(_, m)| **m != AIR)
+            .map(move |(i, m)| (self.base + i as i32, *m))
+    }
+}
+
 // a set of blocks within some rectangle in the x,y plane
 struct Chunk {
     bounds: Bounds,
-    blocks: Vec<Block>,
+    // one column per (x, y) position, in row-major order
+    columns: Vec<Column>,
+    // set once blocks have been placed or removed after generation
+    // edited chunks are never evicted, since regenerating them would lose the edits
+    edited: bool,
+    // for each column, the height below which it's shaded from the sun by other columns
+    // empty when it needs to be recomputed
+    shadow: Vec<f32>,
 }
 
 impl Chunk {
     pub fn new(bounds: Bounds) -> Self {
-        Self { bounds, blocks: Vec::new(), }
+        let columns = (0..CHUNK_SIZE * CHUNK_SIZE).map(|_| Column::default()).collect();
+        Self { bounds, columns, edited: false, shadow: Vec::new() }
+    }
+
+    fn column_index(&self, x: i32, y: i32) -> usize {
+        ((y - self.bounds.y.0) * CHUNK_SIZE + (x - self.bounds.x.0)) as usize
     }
+
     pub fn add(&mut self, block: Block) -> Result<(), &str> {
-        if !self.bounds.contains(&block) {
+        if !self.bounds.contains(block.origin) {
             return Err("Block not within chunk bounds");
         }
-        self.blocks.push(block);
+        let [x, y, z] = block.origin;
+        let idx = self.column_index(x, y);
+        self.columns[idx].set(z, block.material);
         Ok(())
     }
 
-    pub fn process_slices<'a>(&'a self, slices: &mut HashMap<SliceKey, Slice<'a>>) {
-        for b in self.blocks.iter() {
+    pub fn get(&self, pos: Pos3) -> Option<Block> {
+        self.column(pos[0], pos[1])?.get(pos[2]).map(
+            |material| Block { origin: pos, material }
+        )
+    }
+
+    pub fn remove(&mut self, pos: Pos3) -> Option<Block> {
+        if !self.bounds.contains(pos) {
+            return None;
+        }
+        let idx = self.column_index(pos[0], pos[1]);
+        self.columns[idx].remove(pos[2]).map(
+            |material| Block { origin: pos, material }
+        )
+    }
+
+    pub fn column(&self, x: i32, y: i32) -> Option<&Column> {
+        if !self.bounds.contains([x, y, 0]) {
+            return None;
+        }
+        Some(&self.columns[self.column_index(x, y)])
+    }
+
+    pub fn set_column(&mut self, x: i32, y: i32, column: Column) {
+        let idx = self.column_index(x, y);
+        self.columns[idx] = column;
+    }
+
+    pub fn blocks(&self) -> impl Iterator<Item = Block> + '_ {
+        self.columns.iter().enumerate().flat_map(
+            move |(idx, column)| {
+                let x = self.bounds.x.0 + idx as i32 % CHUNK_SIZE;
+                let y = self.bounds.y.0 + idx as i32 / CHUNK_SIZE;
+                column.iter().map(move |(z, material)| Block { origin: [x, y, z], material })
+            }
+        )
+    }
+
+    // keeps the frontmost slice in each position, with translucent blocks' slices kept separately from opaque ones
+    // slices are positioned in view coordinates for the given rotation
+    // shadowed tells whether the face of a block that a slice index belongs to is out of the sun
+    pub fn process_slices(
+        &self, materials: &Materials, rotation: Rotation, shadowed: impl Fn(&Block, u8) -> bool,
+        slices: &mut HashMap<SliceKey, Slice>, translucent: &mut HashMap<SliceKey, Slice>,
+    ) {
+        for world_block in self.blocks() {
+            let b = Block { origin: rotation.turn_block(world_block.origin), ..world_block };
+            let is_translucent = materials.get(b.material).is_some_and(|m| m.translucent());
+            let slices = if is_translucent { &mut *translucent } else { &mut *slices };
             for index in 0..6 {
-                let (key, val) = Slice::create(index, b);
+                let (key, val) = Slice::create(index, b, shadowed(&world_block, index));
                 match slices.get(&key) {
-                    Some(other) => if b.draw_after(other.parent) {
+                    Some(other) => if b.draw_after(&other.parent) {
                         slices.insert(key, val);
                     },
                     None => { slices.insert(key, val); },
@@ -77,6 +234,52 @@ impl Chunk {
         }
     }
 }
+// which way the camera faces, in quarter turns of the scene clockwise on screen from the default view
+// blocks are drawn in view coordinates, i.e. with the world turned so the camera looks at it from the default direction
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Rotation(u8);
+
+impl Rotation {
+    pub fn turned(self, clockwise: bool) -> Self {
+        Self((self.0 + if clockwise { 1 } else { 3 }) % 4)
+    }
+
+    pub fn inverse(self) -> Self {
+        Self((4 - self.0) % 4)
+    }
+
+    // turns an offset in the x,y plane
+    pub fn turn(self, [x, y]: Pos2) -> Pos2 {
+        match self.0 {
+            0 => [x, y],
+            1 => [-y, x],
+            2 => [-x, -y],
+            _ => [y, -x],
+        }
+    }
+
+    pub fn turn_point(self, [x, y]: Vertex) -> Vertex {
+        match self.0 {
+            0 => [x, y],
+            1 => [-y, x],
+            2 => [-x, -y],
+            _ => [y, -x],
+        }
+    }
+
+    // origin of a block after turning, which is a different corner of the turned block unless there's no rotation
+    pub fn turn_block(self, [x, y, z]: Pos3) -> Pos3 {
+        // turn the block's center, which stays the center
+        let [cx, cy] = self.turn([2 * x + 1, 2 * y + 1]);
+        [(cx - 1) / 2, (cy - 1) / 2, z]
+    }
+
+    pub fn turn_offset(self, [dx, dy, dz]: Pos3) -> Pos3 {
+        let [dx, dy] = self.turn([dx, dy]);
+        [dx, dy, dz]
+    }
+}
+
 pub struct ProjectionMatrix(f32, f32, f32, f32);
 
 impl ProjectionMatrix {
@@ -112,6 +315,10 @@ pub struct Camera {
     // pixels per block edge
     pub scale: f32,
     pub proj_matrix: ProjectionMatrix,
+    pub rotation: Rotation,
+    // how far the origin was rounded by the last zoom, so the next zoom can start from where it should be
+    // without this, the point under the cursor drifts a little with every zoom
+    pub zoom_remainder: Vertex,
 }
 
 impl Camera {
@@ -121,50 +328,118 @@ impl Camera {
             height, width,
             scale,
             proj_matrix: ProjectionMatrix::new(scale),
+            rotation: Rotation::default(),
+            zoom_remainder: [0., 0.],
         }
     }
 
-    // determines which chunks in an array of chunks are (at least partially) in view
+    // a camera looking from the same direction at a different part of the screen space
+    pub fn region(&self, origin: Pos2, height: usize, width: usize) -> Self {
+        Camera { rotation: self.rotation, ..Camera::new(origin, height, width, self.scale) }
+    }
+
+    // the same view with factor times as many pixels along each side
+    pub fn supersampled(&self, factor: usize) -> Self {
+        let f = factor as i32;
+        self.region([self.origin[0] * f, self.origin[1] * f], self.height * factor, self.width * factor)
+            .with_scale(self.scale * factor as f32)
+    }
+
+    fn with_scale(self, scale: f32) -> Self {
+        Camera { scale, proj_matrix: ProjectionMatrix::new(scale), ..self }
+    }
+
+    // the world point at z = 0 that is drawn at screen position (x, y)
+    pub fn world_point(&self, x: f32, y: f32) -> Vertex {
+        self.rotation.inverse().turn_point(self.to_plane(x, y))
+    }
+
+    // multiplies the scale by factor, within limits, keeping the point drawn at screen position (x, y) in place
+    // factors that aren't finite and positive are ignored
+    pub fn zoom(&mut self, factor: f32, x: f32, y: f32) {
+        if !factor.is_finite() || factor <= 0. {
+            return;
+        }
+        let [rx, ry] = self.zoom_remainder;
+        let focus = self.to_plane(x + rx, y + ry);
+        self.scale = (self.scale * factor).clamp(MIN_SCALE, MAX_SCALE);
+        self.proj_matrix = ProjectionMatrix::new(self.scale);
+        let [fx, fy] = self.proj_matrix.proj(focus);
+        let (ox, oy) = (fx - x, fy - y);
+        self.origin = [ox.round() as i32, oy.round() as i32];
+        self.zoom_remainder = [ox - ox.round(), oy - oy.round()];
+    }
+
+    // turns the view a quarter turn, keeping the world point at the center of the screen in place
+    pub fn rotate(&mut self, clockwise: bool) {
+        let center = self.world_point(self.width as f32 / 2., self.height as f32 / 2.);
+        self.rotation = self.rotation.turned(clockwise);
+        self.look_at(center);
+    }
+
+    // moves the view so that a world point at z = 0 is drawn at the center of the screen
+    pub fn look_at(&mut self, point: Vertex) {
+        let [x, y] = self.proj_matrix.proj(self.rotation.turn_point(point));
+        self.origin = [
+            (x - self.width as f32 / 2.).round() as i32,
+            (y - self.height as f32 / 2.).round() as i32,
+        ];
+        self.zoom_remainder = [0., 0.];
+    }
+
+    // the point at z = 0, in view coordinates, that is drawn at screen position (x, y)
+    fn to_plane(&self, x: f32, y: f32) -> Vertex {
+        let [x0, y0] = to_vertex(self.origin);
+        self.proj_matrix.inverse().proj([x0 + x, y0 + y])
+    }
+
+    // determines the keys of chunks that are (at least partially) in view
     // assumes that hash map keys are same as chunk origins, and chunk bounds are divisible by CHUNK_SIZE
-    fn in_view<'a>(&self, chunks: &'a HashMap<Pos2, Chunk>) -> Vec<&'a Chunk> {
+    // z_range is the range of heights blocks can have; a block at height z is drawn where (x - z, y - z) would be at z = 0
+    fn in_view(&self, z_range: (i32, i32)) -> Vec<Pos2> {
         // first get coordinates of screen bounds at z = 0
-        let [x0, y0] = to_vertex(self.origin);
-        let inv_proj = self.proj_matrix.inverse();
-        let top_left = inv_proj.proj([x0, y0]);
-        let top_right = inv_proj.proj([x0 + self.width as f32, y0]);
-        let bottom_left = inv_proj.proj([x0, y0 + self.height as f32]);
-        let bottom_right = inv_proj.proj([x0 + self.width as f32, y0 + self.height as f32]);
-        // take extreme values and round to surrounding multiples of CHUNK_SIZE
-        let x_min = round_down(top_left[0] as i32, CHUNK_SIZE);
-        let x_max = round_up(bottom_right[0] as i32, CHUNK_SIZE);
-        let y_min = round_down(top_right[1] as i32, CHUNK_SIZE);
-        let y_max = round_up(bottom_left[1] as i32, CHUNK_SIZE);
-        let mut chunks_out = Vec::<&Chunk>::new();
+        let (w, h) = (self.width as f32, self.height as f32);
+        let top_left = self.to_plane(0., 0.);
+        let top_right = self.to_plane(w, 0.);
+        let bottom_left = self.to_plane(0., h);
+        let bottom_right = self.to_plane(w, h);
+        // take extreme values and widen by the height range
+        let (z_min, z_max) = z_range;
+        let view_min = [top_left[0].floor() as i32 + z_min, top_right[1].floor() as i32 + z_min];
+        let view_max = [bottom_right[0].ceil() as i32 + z_max, bottom_left[1].ceil() as i32 + z_max];
+        // turn the corners back into world coordinates, and round to surrounding multiples of CHUNK_SIZE
+        let to_world = self.rotation.inverse();
+        let [ax, ay, _] = to_world.turn_block([view_min[0], view_min[1], 0]);
+        let [bx, by, _] = to_world.turn_block([view_max[0], view_max[1], 0]);
+        let x_min = round_down(ax.min(bx), CHUNK_SIZE);
+        let x_max = round_up(ax.max(bx) + 1, CHUNK_SIZE);
+        let y_min = round_down(ay.min(by), CHUNK_SIZE);
+        let y_max = round_up(ay.max(by) + 1, CHUNK_SIZE);
+        let mut keys = Vec::new();
         for x in (x_min..x_max).step_by(CHUNK_SIZE as usize) {
             for y in (y_min..y_max).step_by(CHUNK_SIZE as usize) {
-                match chunks.get(&[x, y]) {
-                    Some(chunk) => chunks_out.push(chunk),
-                    None => (),
-                }
+                keys.push([x, y]);
             }
         }
 
-        chunks_out
+        keys
     }
 }
 
 // represents one of 6 triangular slices that makes up the 2d isometric view of a block
-struct Slice<'a> {
+struct Slice {
     pos: Pos2,
     index: u8,  // 0 through 5, starting with top-left slice and going clockwise
-    parent: &'a Block,
+    parent: Block,
+    // whether the face this slice is part of is out of the sun
+    shadowed: bool,
 }
 
 #[derive(Eq, Hash, PartialEq)]
 struct SliceKey(i32, i32, bool);
 
-impl<'a> Slice<'a> {
-    fn create(index: u8, parent: &'a Block) -> (SliceKey, Slice<'a>) {
+impl Slice {
+    fn create(index: u8, parent: Block, shadowed: bool) -> (SliceKey, Slice) {
         assert!(index < 6);
         let pos3 = if index == 0 || index == 5 {
             parent.origin
@@ -176,92 +451,739 @@ impl<'a> Slice<'a> {
             [parent.origin[0], parent.origin[1], parent.origin[2] - 1]
         };
         let pos = [pos3[0] - pos3[2], pos3[1] - pos3[2]];
-        (SliceKey(pos[0], pos[1], index % 2 == 0), Self { pos, index, parent })
+        (SliceKey(pos[0], pos[1], index.is_multiple_of(2)), Self { pos, index, parent, shadowed })
+    }
+    // offset, in view coordinates, to the block that would cover the face this slice is part of
+    fn facing(index: u8) -> Pos3 {
+        view_normal(Slice::face(index))
+    }
+    fn face(index: u8) -> Face {
+        match index {
+            0 | 5 => Face::Top,
+            1 | 2 => Face::Right,
+            _ => Face::Left,
+        }
+    }
+    // distance toward the viewer of a point in the plane that lies on this slice's face
+    // for a point (x, y, z) this is x + y + z; the plane point is (x - z, y - z)
+    fn depth_at(&self, p: Pos2) -> f32 {
+        let [x, y, z] = self.parent.origin;
+        let d = match self.index {
+            0 | 5 => p[0] + p[1] + 3 * z,
+            1 | 2 => p[1] - 2 * p[0] + 3 * (x + 1),
+            _ => p[0] - 2 * p[1] + 3 * (y + 1),
+        };
+        d as f32
     }
     fn points_right(&self) -> bool {
-        self.index % 2 == 0
+        self.index.is_multiple_of(2)
     }
-    fn color(&self) -> Color {
-        if self.index == 0 || self.index == 5 {
-            return self.parent.color.clone();
-        } else if self.index == 1 || self.index == 2 {
-            return self.parent.color.scaled(0.8);
+    // corners of the triangle in the plane
+    fn vertices(&self) -> [Pos2; 3] {
+        let [u, v] = self.pos;
+        if self.points_right() {
+            [[u, v], [u + 1, v], [u + 1, v + 1]]
         } else {
-            return self.parent.color.scaled(0.9);
+            [[u, v], [u + 1, v + 1], [u, v + 1]]
         }
     }
-
-    fn draw(&self, proj_matrix: &ProjectionMatrix, origin: Pos2, canvas: &mut Canvas) {
-        let v = if self.points_right() {
-            [
-                self.pos,
-                [self.pos[0] + 1, self.pos[1]],
-                [self.pos[0] + 1, self.pos[1] + 1],
-            ]
-        } else {
-            [
-                self.pos,
-                [self.pos[0] + 1, self.pos[1] + 1],
-                [self.pos[0], self.pos[1] + 1],
-            ]
+    // the corner of the block's face, in view coordinates, that is drawn at point p of the plane
+    fn corner(&self, p: Pos2) -> Pos3 {
+        let [x, y, z] = self.parent.origin;
+        match self.index {
+            0 | 5 => [p[0] + z, p[1] + z, z],
+            1 | 2 => [x + 1, p[1] + x + 1 - p[0], x + 1 - p[0]],
+            _ => [p[0] + y + 1 - p[1], y + 1, y + 1 - p[1]],
+        }
+    }
+    // the point of an atlas tile, from (0, 0) at its top left to (1, 1) at its bottom right, at a corner of the face
+    // sides are upright, with their tops along the top of the block
+    fn texture_coords(&self, corner: Pos3) -> [f32; 2] {
+        let [x, y, z] = self.parent.origin;
+        let [cx, cy, cz] = corner;
+        let st = match self.index {
+            0 | 5 => [cx - x, cy - y],
+            1 | 2 => [y + 1 - cy, z - cz],
+            _ => [cx - x, z - cz],
         };
+        st.map(|c| c as f32)
+    }
+    // color of the face under the scene's light and the material's shading of the face, before ambient occlusion
+    fn color(&self, base: &Color, material: &Material, lighting: &FaceLighting) -> Color {
+        if material.emissive {
+            return base.clone();
+        }
+        let face = Slice::face(self.index) as usize;
+        let light = if self.shadowed { lighting.shadowed } else { lighting.lit[face] };
+        base.lit(light.map(|c| c * material.shading[face]))
+    }
+
+    // occlusion is the brightness at each vertex from ambient occlusion
+    fn draw(
+        &self, proj_matrix: &ProjectionMatrix, origin: Pos2, materials: &Materials, lighting: &FaceLighting,
+        occlusion: [f32; 3], canvas: &mut Canvas,
+    ) {
+        let v = self.vertices();
         let mut vertices = [[0f32; 2]; 3];
         let o32 = to_vertex(origin);
         for i in 0..3 {
             let proj = proj_matrix.proj([v[i][0] as f32, v[i][1] as f32]);
             vertices[i] = [proj[0] - o32[0], proj[1] - o32[1]];
         }
-        Triangle::new(vertices, self.color()).draw(canvas);
+        let material = materials.get(self.parent.material).expect("Block has unknown material");
+        // a textured face lights its texels instead of the material's color
+        let tile = material.tiles.map(|tiles| tiles[Slice::face(self.index) as usize]);
+        let texture = materials.atlas().zip(tile).filter(|(atlas, tile)| (*tile as usize) < atlas.tile_count());
+        let base = if texture.is_some() { Color::from_hex(0xffffff) } else { material.color.clone() };
+        let color = self.color(&base, material, lighting);
+        let triangle = if canvas.has_depth() {
+            let depths = [self.depth_at(v[0]), self.depth_at(v[1]), self.depth_at(v[2])];
+            Triangle::with_depth(vertices, depths, color.clone())
+        } else {
+            Triangle::new(vertices, color.clone())
+        };
+        let triangle = if material.emissive {
+            triangle
+        } else {
+            triangle.with_colors(occlusion.map(|o| color.lit([o; 3])))
+        };
+        let triangle = match texture {
+            Some((atlas, tile)) => triangle.textured(atlas, tile, v.map(|p| self.texture_coords(self.corner(p)))),
+            None => triangle,
+        };
+        triangle.translucent(material.alpha).blended(material.blend).draw(canvas);
+    }
+}
+
+// brightness of faces as drawn by some camera
+struct FaceLighting {
+    // for faces drawn on top, left and right, in the order of `Face`'s variants
+    lit: [[f32; 3]; 3],
+    // for faces the sun doesn't reach
+    shadowed: [f32; 3],
+}
+
+// the steps taken from a column toward the sun when looking for columns that shade it
+struct SunRay {
+    // horizontal offset per step, with the larger of its components being 1
+    step: [f32; 2],
+    // how much the ray rises per step
+    rise: f32,
+}
+
+impl SunRay {
+    // nothing if the sun is at or below the horizon
+    fn of(light: &Light) -> Option<Self> {
+        if light.sun_z <= 0. {
+            return None;
+        }
+        let longest = light.sun_x.abs().max(light.sun_y.abs());
+        if longest == 0. {
+            // straight overhead, so nothing is shaded
+            return Some(Self { step: [0., 0.], rise: f32::INFINITY });
+        }
+        Some(Self { step: [light.sun_x / longest, light.sun_y / longest], rise: light.sun_z / longest })
+    }
+
+    // the column the ray from the center of column (x, y) is over after some number of steps
+    // negative steps go away from the sun
+    fn column(&self, x: i32, y: i32, steps: i32) -> Pos2 {
+        [
+            (x as f32 + 0.5 + steps as f32 * self.step[0]).floor() as i32,
+            (y as f32 + 0.5 + steps as f32 * self.step[1]).floor() as i32,
+        ]
+    }
+}
+
+// offset, in view coordinates, to the block that would cover a face drawn facing the given way
+pub fn view_normal(face: Face) -> Pos3 {
+    match face {
+        Face::Top => [0, 0, 1],
+        Face::Right => [1, 0, 0],
+        Face::Left => [0, 1, 0],
+    }
+}
+
+// key of the chunk containing pos
+fn chunk_key(pos: Pos3) -> Pos2 {
+    [round_down(pos[0], CHUNK_SIZE), round_down(pos[1], CHUNK_SIZE)]
+}
+
+// number of subsurface blocks between the surface and the stone below it
+const SOIL_DEPTH: i32 = 3;
+
+// net deposition, in blocks, above which erosion leaves a layer of silt on the surface
+const SILT_DEPTH: f32 = 0.5;
+
+// the column of terrain from min_height up to height, with water on top of it up to water_level
+// sediment is how much material erosion left on the column
+fn terrain_column(height: f32, min_height: i32, water_level: i32, biome: Biome, sediment: f32) -> Column {
+    let top = height as i32;
+    let surface = if sediment >= SILT_DEPTH && biome != Biome::Snowcap && biome != Biome::Cliff {
+        SILT
+    } else {
+        biome.surface()
+    };
+    let mut column = Column::default();
+    for z in (top + 1)..=water_level {
+        column.set(z, WATER);
+    }
+    for z in min_height..=top {
+        let material = if z == top {
+            surface
+        } else if top - z <= SOIL_DEPTH {
+            biome.subsurface()
+        } else {
+            STONE
+        };
+        column.set(z, material);
+    }
+    column
+}
+
+// settings for generating chunks on demand, for scenes that aren't limited to a fixed heightmap
+struct ChunkGenerator {
+    noise: NoiseLayers,
+    // seed for the climate fields
+    seed: u64,
+    thresholds: BiomeThresholds,
+    min_height: i32,
+    // there's no way to tell which low areas are enclosed in an unbounded scene, so everything below this is water
+    water_level: i32,
+    // max number of chunks to keep loaded; chunks furthest from the view are evicted first
+    chunk_budget: usize,
+}
+
+impl ChunkGenerator {
+    fn generate(&self, key: Pos2) -> Chunk {
+        // generate a one block border around the chunk, so slopes at its edges match its neighbours
+        let size = CHUNK_SIZE as usize + 2;
+        let (x0, y0) = (key[0] - 1, key[1] - 1);
+        let h = self.noise.heightmap(x0, y0, size, size);
+        let climate = Climate::sample(x0, y0, size, size, self.seed);
+        let biomes = classify(&h, &climate, &self.thresholds);
+        let mut chunk = Chunk::new(Bounds::of_chunk(key));
+        h.data.iter().zip(biomes).enumerate().for_each(
+            |(idx, (height, biome))| {
+                let (i, j) = (idx / size, idx % size);
+                if i == 0 || j == 0 || i == size - 1 || j == size - 1 {
+                    return;
+                }
+                let column = terrain_column(*height, self.min_height, self.water_level, biome, 0.);
+                chunk.set_column(x0 + j as i32, y0 + i as i32, column);
+            }
+        );
+
+        chunk
     }
 }
 
 pub struct Scene {
     chunks: HashMap<Pos2, Chunk>,
+    // lowest and highest z values of blocks in the scene
+    z_range: (i32, i32),
+    generator: Option<ChunkGenerator>,
+    // rows and columns of the heightmap a bounded scene was built from; generated scenes have no size
+    size: Option<[usize; 2]>,
+    materials: Materials,
+    // resolve visibility per pixel with a depth buffer, rather than keeping the frontmost slice in each position
+    // slower, but doesn't rely on everything being a unit cube on the grid
+    pub depth_buffer: bool,
+    // darken the corners of faces that other blocks crowd around
+    pub ambient_occlusion: bool,
+    // pixels drawn along each side of every canvas pixel, which are averaged to smooth edges; 1 draws each pixel once
+    pub supersampling: usize,
+    light: Light,
 }
 
 impl Scene {
-    fn add(&mut self, b: Block) {
-        let chunk_x0 = round_down(b.origin[0], CHUNK_SIZE);
-        let chunk_y0 = round_down(b.origin[1], CHUNK_SIZE);
-        if let Some(chunk) = self.chunks.get_mut(&[chunk_x0, chunk_y0]) {
-            chunk.add(b).unwrap();
-        } else {
-            let mut chunk = Chunk::new(Bounds{ x: (chunk_x0, chunk_x0 + CHUNK_SIZE), y: (chunk_y0, chunk_y0 + CHUNK_SIZE) });
-            chunk.add(b).unwrap();
-            self.chunks.insert([chunk_x0, chunk_y0], chunk);
+    fn add_column(&mut self, x: i32, y: i32, column: Column) {
+        if let Some(top) = column.top() {
+            self.z_range = (self.z_range.0.min(column.base), self.z_range.1.max(top));
         }
+        let key = chunk_key([x, y, 0]);
+        self.chunks.entry(key)
+            .or_insert_with(|| Chunk::new(Bounds::of_chunk(key)))
+            .set_column(x, y, column);
     }
 
-    pub fn from_heightmap(h: Heightmap, min_height: i32) -> Self {
+    // voxelises a heightmap, with the sea at water_level past its edges and lakes filling any enclosed basins
+    // if the heightmap was eroded, sediment marks where material was deposited
+    pub fn from_heightmap(
+        h: Heightmap, min_height: i32, water_level: i32, climate: &Climate, thresholds: &BiomeThresholds,
+        sediment: Option<&ErosionRecord>,
+    ) -> Self {
         let mut scene = Scene {
             chunks: HashMap::new(),
+            z_range: (min_height, min_height),
+            generator: None,
+            size: Some([h.rows, h.cols]),
+            materials: Materials::default(),
+            depth_buffer: false,
+            ambient_occlusion: true,
+            supersampling: 1,
+            light: Light::default(),
         };
-        h.data.iter().enumerate().for_each(
-            |(idx, height)| {
+        let biomes = classify(&h, climate, thresholds);
+        // columns with a NaN height are left out; water passes over them as if they were a pit
+        let tops: Vec<i32> = h.data.iter().map(|height| if height.is_nan() { min_height - 1 } else { *height as i32 }).collect();
+        let water = water_levels(&tops, h.rows, h.cols, water_level);
+        h.data.iter().zip(biomes).enumerate().filter(|(_, (height, _))| !height.is_nan()).for_each(
+            |(idx, (height, biome))| {
                 let (i, j) = (idx / h.cols, idx % h.cols);
-                for z in min_height..=(*height as i32) {
-                    let x = (256. * (1. / ((1 + z - min_height) as f32).powf(0.35))) as u8;
-                    scene.add(Block {
-                        origin: [j as i32, i as i32, z],
-                        color: Color { r: x, g: x, b: x },
-                    });
-                }
+                let deposited = sediment.map_or(0., |record| record.sediment(idx));
+                let column = terrain_column(*height, min_height, water[idx], biome, deposited);
+                scene.add_column(j as i32, i as i32, column);
             }
         );
 
         scene
     }
 
-    pub fn draw(&self, camera: &Camera) -> Canvas {
+    // an unbounded scene, whose chunks are generated from noise as they come into view
+    pub fn generated(
+        noise: NoiseLayers, seed: u64, thresholds: BiomeThresholds, min_height: i32, water_level: i32, chunk_budget: usize,
+    ) -> Self {
+        let z_max = (noise.max_amplitude().ceil() as i32).max(water_level);
+        Scene {
+            chunks: HashMap::new(),
+            z_range: (min_height, z_max),
+            generator: Some(ChunkGenerator { noise, seed, thresholds, min_height, water_level, chunk_budget }),
+            size: None,
+            materials: Materials::default(),
+            depth_buffer: false,
+            ambient_occlusion: true,
+            supersampling: 1,
+            light: Light::default(),
+        }
+    }
+
+    // how the light falls on each face as drawn by a camera with the given rotation
+    fn face_lighting(&self, rotation: Rotation) -> FaceLighting {
+        let to_world = rotation.inverse();
+        FaceLighting {
+            lit: [Face::Top, Face::Left, Face::Right].map(
+                |face| self.light.brightness(to_world.turn_offset(view_normal(face)))
+            ),
+            shadowed: [self.light.ambient; 3],
+        }
+    }
+
+    pub fn light(&self) -> Light {
+        self.light
+    }
+
+    // changes the lighting, which means recomputing every shadow
+    pub fn set_light(&mut self, light: Light) {
+        self.light = light;
+        for chunk in self.chunks.values_mut() {
+            chunk.shadow.clear();
+        }
+    }
+
+    // the height below which column (x, y) is shaded by the columns between it and the sun
+    fn shadow_height(&self, x: i32, y: i32, ray: &SunRay) -> f32 {
+        let (z_min, z_max) = (self.z_range.0 as f32, self.z_range.1 as f32);
+        let mut height = f32::NEG_INFINITY;
+        let mut steps = 1;
+        // stop once the ray is above every block, or above a shadow that's already been found
+        while steps <= MAX_SHADOW_LENGTH && z_max - steps as f32 * ray.rise > height.max(z_min - 1.) {
+            let [cx, cy] = ray.column(x, y, steps);
+            if let Some(top) = self.column_height(cx, cy) {
+                height = height.max(top as f32 - steps as f32 * ray.rise);
+            }
+            steps += 1;
+        }
+        height
+    }
+
+    // computes shadows for any of the given chunks that don't have them
+    fn update_shadows(&mut self, keys: &[Pos2]) {
+        let ray = match SunRay::of(&self.light) {
+            Some(ray) => ray,
+            None => return,
+        };
+        for key in keys {
+            if !self.chunks.get(key).is_some_and(|chunk| chunk.shadow.is_empty()) {
+                continue;
+            }
+            let shadow = (0..CHUNK_SIZE * CHUNK_SIZE).map(
+                |idx| self.shadow_height(key[0] + idx % CHUNK_SIZE, key[1] + idx / CHUNK_SIZE, &ray)
+            ).collect();
+            self.chunks.get_mut(key).unwrap().shadow = shadow;
+        }
+    }
+
+    // recomputes the shadows that column (x, y) could fall on, after a change to it
+    fn update_shadows_near(&mut self, x: i32, y: i32) {
+        let ray = match SunRay::of(&self.light) {
+            Some(ray) => ray,
+            None => return,
+        };
+        let reach = ((self.z_range.1 - self.z_range.0 + 1) as f32 / ray.rise).ceil().min(MAX_SHADOW_LENGTH as f32) as i32;
+        let mut columns = HashSet::new();
+        for steps in 0..=reach {
+            let [cx, cy] = ray.column(x, y, -steps);
+            // rays from neighbouring columns can pass over this one as well
+            for (dx, dy) in (-1..=1).flat_map(|dx| (-1..=1).map(move |dy| (dx, dy))) {
+                columns.insert([cx + dx, cy + dy]);
+            }
+        }
+        for [cx, cy] in columns {
+            let height = self.shadow_height(cx, cy, &ray);
+            if let Some(chunk) = self.chunks.get_mut(&chunk_key([cx, cy, 0])) {
+                if !chunk.shadow.is_empty() {
+                    let idx = chunk.column_index(cx, cy);
+                    chunk.shadow[idx] = height;
+                }
+            }
+        }
+    }
+
+    // brightness at each of a slice's vertices, darker the more solid blocks are around that corner of its face
+    // looks at the blocks in front of the face that share the corner: the two beside the one directly in front, and
+    // the one diagonal to it
+    fn occlusion(&self, slice: &Slice, rotation: Rotation) -> [f32; 3] {
+        if !self.ambient_occlusion {
+            return [1.; 3];
+        }
+        let to_world = rotation.inverse();
+        let solid = |pos: Pos3| self.get(to_world.turn_block(pos)).is_some_and(
+            |b| self.materials.get(b.material).is_some_and(|m| !m.transparent)
+        );
+        let normal = Slice::facing(slice.index);
+        let [x, y, z] = slice.parent.origin;
+        let front = [x + normal[0], y + normal[1], z + normal[2]];
+        let tangents: Vec<usize> = (0..3).filter(|&axis| normal[axis] == 0).collect();
+        slice.vertices().map(
+            |v| {
+                let p = slice.corner(v);
+                // the other block touching the corner along an axis; blocks span z - 1 to z, but x to x + 1
+                let across = |axis: usize| {
+                    let (low, high) = if axis == 2 { (p[2], p[2] + 1) } else { (p[axis] - 1, p[axis]) };
+                    if front[axis] == low { high } else { low }
+                };
+                let mut side0 = front;
+                side0[tangents[0]] = across(tangents[0]);
+                let mut side1 = front;
+                side1[tangents[1]] = across(tangents[1]);
+                let mut diagonal = side0;
+                diagonal[tangents[1]] = across(tangents[1]);
+                let (side0, side1) = (solid(side0), solid(side1));
+                let count = if side0 && side1 { 3 } else { side0 as usize + side1 as usize + solid(diagonal) as usize };
+                OCCLUSION_LEVELS[count]
+            }
+        )
+    }
+
+    // whether the face of block b (in world coordinates) that a slice index belongs to is out of the sun
+    fn is_shadowed(&self, b: &Block, index: u8, rotation: Rotation) -> bool {
+        if self.light.sun_z <= 0. {
+            return true;
+        }
+        let [dx, dy, dz] = rotation.inverse().turn_offset(Slice::facing(index));
+        let [x, y, z] = b.origin;
+        // a side face is lit by light passing over the column in front of it, halfway up the block
+        let (cx, cy) = (x + dx, y + dy);
+        let height = if dz == 1 { z as f32 } else { z as f32 - 0.5 };
+        let shadow = self.chunks.get(&chunk_key([cx, cy, 0]))
+            .filter(|chunk| !chunk.shadow.is_empty())
+            .map_or(f32::NEG_INFINITY, |chunk| chunk.shadow[chunk.column_index(cx, cy)]);
+        height < shadow
+    }
+
+    pub fn materials(&self) -> &Materials {
+        &self.materials
+    }
+
+    pub fn materials_mut(&mut self) -> &mut Materials {
+        &mut self.materials
+    }
+
+    fn get(&self, pos: Pos3) -> Option<Block> {
+        self.chunks.get(&chunk_key(pos)).and_then(|chunk| chunk.get(pos))
+    }
+
+    // the chunk containing pos, generating it first if the scene has a generator
+    // so that edits aren't later overwritten by generated terrain
+    fn chunk_for_edit(&mut self, pos: Pos3) -> &mut Chunk {
+        let key = chunk_key(pos);
+        let Scene { chunks, generator, .. } = self;
+        let chunk = chunks.entry(key).or_insert_with(
+            || match generator {
+                Some(g) => g.generate(key),
+                None => Chunk::new(Bounds::of_chunk(key)),
+            }
+        );
+        chunk.edited = true;
+        chunk
+    }
+
+    // places a block at pos, replacing any block already there
+    // returns false without placing anything if the material isn't a known, non-air material
+    // or pos is above WORLD_TOP or below WORLD_BOTTOM
+    pub fn place(&mut self, pos: Pos3, material: MaterialId) -> bool {
+        if material == AIR || self.materials.get(material).is_none() || !(WORLD_BOTTOM..=WORLD_TOP).contains(&pos[2]) {
+            return false;
+        }
+        self.z_range = (self.z_range.0.min(pos[2]), self.z_range.1.max(pos[2]));
+        self.chunk_for_edit(pos).add(Block { origin: pos, material }).unwrap();
+        self.update_shadows_near(pos[0], pos[1]);
+        true
+    }
+
+    // removes the block at pos, returning whether there was one
+    pub fn remove(&mut self, pos: Pos3) -> bool {
+        if self.get(pos).is_none() {
+            return false;
+        }
+        let removed = self.chunk_for_edit(pos).remove(pos).is_some();
+        self.update_shadows_near(pos[0], pos[1]);
+        removed
+    }
+
+    // finds the block and face drawn at pixel (px, py)
+    // walks down through the blocks whose slices could land on that pixel, front to back, and returns the first that exists
+    // the returned position is in world coordinates, while the face is the side of the block as drawn on screen
+    pub fn pick(&self, camera: &Camera, px: usize, py: usize) -> Option<(Pos3, Face)> {
+        let [u, v] = camera.to_plane(px as f32 + 0.5, py as f32 + 0.5);
+        let to_world = camera.rotation.inverse();
+        let (cu, cv) = (u.floor() as i32, v.floor() as i32);
+        let points_right = u - u.floor() >= v - v.floor();
+        // candidate slice indices in draw order, with the offset from (cu + z, cv + z, z) to the block they'd belong to
+        let candidates: [(u8, Pos2); 3] = if points_right {
+            [(0, [0, 0]), (4, [0, -1]), (2, [-1, -1])]
+        } else {
+            [(5, [0, 0]), (1, [-1, 0]), (3, [-1, -1])]
+        };
+        for z in (self.z_range.0..=self.z_range.1).rev() {
+            for (index, [dx, dy]) in candidates {
+                let pos = to_world.turn_block([cu + z + dx, cv + z + dy, z]);
+                if self.get(pos).is_some() {
+                    return Some((pos, Slice::face(index)));
+                }
+            }
+        }
+        None
+    }
+
+    pub fn material_at(&self, pos: Pos3) -> Option<MaterialId> {
+        self.get(pos).map(|b| b.material)
+    }
+
+    pub fn size(&self) -> Option<[usize; 2]> {
+        self.size
+    }
+
+    // z of the highest block at (x, y) that isn't a liquid, so seas and lakes give the ground beneath them
+    pub fn ground_height(&self, x: i32, y: i32) -> Option<i32> {
+        let column = self.chunks.get(&chunk_key([x, y, 0]))?.column(x, y)?;
+        column.iter()
+            .filter(|(_, m)| !self.materials.get(*m).is_some_and(|m| m.liquid))
+            .map(|(z, _)| z)
+            .last()
+    }
+
+    // the ground height of every column of a bounded scene, as it is now, with NaN where there's no ground
+    // nothing for generated scenes, which have no edges
+    pub fn surface(&self) -> Option<Heightmap> {
+        let [rows, cols] = self.size?;
+        let data = (0..rows)
+            .flat_map(|i| (0..cols).map(move |j| (i as i32, j as i32)))
+            .map(|(y, x)| self.ground_height(x, y).map_or(f32::NAN, |z| z as f32))
+            .collect();
+        Some(Heightmap { data, rows, cols })
+    }
+
+    // z of the highest block at (x, y), if there are any
+    pub fn column_height(&self, x: i32, y: i32) -> Option<i32> {
+        self.chunks.get(&chunk_key([x, y, 0]))?.column(x, y)?.top()
+    }
+
+    // makes sure chunks with the given keys are loaded, then evicts chunks that aren't needed
+    // does nothing for scenes without a generator
+    fn load(&mut self, keys: &[Pos2]) {
+        let Scene { chunks, generator, .. } = self;
+        let generator = match generator {
+            Some(g) => g,
+            None => return,
+        };
+        let mut generated = Vec::new();
+        for key in keys {
+            if !chunks.contains_key(key) {
+                chunks.insert(*key, generator.generate(*key));
+                generated.push(*key);
+            }
+        }
+        // new chunks can cast shadows on the ones around them
+        let reach = MAX_SHADOW_LENGTH + CHUNK_SIZE;
+        for (key, chunk) in chunks.iter_mut() {
+            if generated.iter().any(|k| (k[0] - key[0]).abs() <= reach && (k[1] - key[1]).abs() <= reach) {
+                chunk.shadow.clear();
+            }
+        }
+        if chunks.len() <= generator.chunk_budget {
+            return;
+        }
+        // evict chunks that are out of view, furthest from the center of the view first
+        let n = keys.len().max(1) as i64;
+        let center = keys.iter().fold([0i64, 0], |acc, k| [acc[0] + k[0] as i64, acc[1] + k[1] as i64]);
+        let center = [center[0] / n, center[1] / n];
+        let dist = |k: &Pos2| (k[0] as i64 - center[0]).pow(2) + (k[1] as i64 - center[1]).pow(2);
+        let mut evictable: Vec<Pos2> = chunks.iter()
+            .filter(|(k, chunk)| !chunk.edited && !keys.contains(k))
+            .map(|(k, _)| *k)
+            .collect();
+        evictable.sort_unstable_by_key(|k| std::cmp::Reverse(dist(k)));
+        let excess = chunks.len() - generator.chunk_budget;
+        for key in evictable.iter().take(excess) {
+            chunks.remove(key);
+        }
+    }
+
+    pub fn draw(&mut self, camera: &Camera) -> Canvas {
+        if self.supersampling > 1 {
+            return self.draw_samples(&camera.supersampled(self.supersampling)).downsampled(self.supersampling);
+        }
+        self.draw_samples(camera)
+    }
+
+    fn draw_samples(&mut self, camera: &Camera) -> Canvas {
+        let keys = camera.in_view(self.z_range);
+        self.load(&keys);
+        self.update_shadows(&keys);
+        if self.depth_buffer {
+            return self.draw_depth_buffered(&keys, camera);
+        }
         let mut slices = HashMap::<SliceKey, Slice>::new();
-        for chunk in camera.in_view(&self.chunks) {
-            chunk.process_slices(&mut slices)
+        let mut translucent = HashMap::<SliceKey, Slice>::new();
+        for key in keys.iter() {
+            if let Some(chunk) = self.chunks.get(key) {
+                chunk.process_slices(
+                    &self.materials, camera.rotation, |b, index| self.is_shadowed(b, index, camera.rotation),
+                    &mut slices, &mut translucent,
+                )
+            }
         }
+        let lighting = self.face_lighting(camera.rotation);
         let mut canvas = Canvas::new(camera.height, camera.width);
-        for (_, slice) in slices.into_iter() {
-            slice.draw(&camera.proj_matrix, camera.origin, &mut canvas);
+        for slice in slices.values() {
+            let occlusion = self.occlusion(slice, camera.rotation);
+            slice.draw(&camera.proj_matrix, camera.origin, &self.materials, &lighting, occlusion, &mut canvas);
+        }
+        // translucent slices go over whatever opaque slice is behind them
+        for (key, slice) in translucent.iter() {
+            if slices.get(key).is_some_and(|opaque| opaque.parent.draw_after(&slice.parent)) {
+                continue;
+            }
+            let occlusion = self.occlusion(slice, camera.rotation);
+            slice.draw(&camera.proj_matrix, camera.origin, &self.materials, &lighting, occlusion, &mut canvas);
+        }
+
+        canvas
+    }
+
+    // whether a block's face is covered by the block next to it, for a slice index in view coordinates
+    // faces next to transparent blocks stay visible, unless both blocks are the same material
+    fn face_hidden(&self, b: &Block, index: u8, rotation: Rotation) -> bool {
+        let [dx, dy, dz] = rotation.inverse().turn_offset(Slice::facing(index));
+        let [x, y, z] = b.origin;
+        match self.get([x + dx, y + dy, z + dz]) {
+            Some(other) => other.material == b.material || self.materials.get(other.material).is_some_and(|m| !m.transparent),
+            None => false,
+        }
+    }
+
+    // draws every exposed face, leaving it to the canvas's depth channel to decide what ends up visible
+    // translucent faces are drawn after all opaque ones, from back to front
+    fn draw_depth_buffered(&self, keys: &[Pos2], camera: &Camera) -> Canvas {
+        let lighting = self.face_lighting(camera.rotation);
+        let mut canvas = Canvas::with_depth(camera.height, camera.width);
+        let mut translucent = Vec::new();
+        for chunk in keys.iter().filter_map(|key| self.chunks.get(key)) {
+            for b in chunk.blocks() {
+                let is_translucent = self.materials.get(b.material).is_some_and(|m| m.translucent());
+                let view_block = Block { origin: camera.rotation.turn_block(b.origin), ..b };
+                for index in 0..6 {
+                    if self.face_hidden(&b, index, camera.rotation) {
+                        continue;
+                    }
+                    let (_, slice) = Slice::create(index, view_block, self.is_shadowed(&b, index, camera.rotation));
+                    if is_translucent {
+                        translucent.push(slice);
+                    } else {
+                        let occlusion = self.occlusion(&slice, camera.rotation);
+                        slice.draw(&camera.proj_matrix, camera.origin, &self.materials, &lighting, occlusion, &mut canvas);
+                    }
+                }
+            }
+        }
+        translucent.sort_by_key(|slice| slice.parent.origin.iter().sum::<i32>());
+        for slice in translucent {
+            let occlusion = self.occlusion(&slice, camera.rotation);
+            slice.draw(&camera.proj_matrix, camera.origin, &self.materials, &lighting, occlusion, &mut canvas);
         }
 
         canvas
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn chunks_stay_within_budget_and_edits_survive() {
+        let noise = NoiseLayers::new(vec![20, 8], vec![6., 3.], 5);
+        let budget = 12;
+        let mut scene = Scene::generated(noise, 5, BiomeThresholds::new(), -8, -2, budget);
+        // a small view only ever needs a few chunks, so anything beyond the budget should be evicted
+        let camera = |x: i32| Camera::new([x, 0], 32, 32, 8.);
+        scene.draw(&camera(0));
+        let first: Vec<Pos2> = scene.chunks.keys().copied().collect();
+        let edit = [3, 3, 10];
+        assert!(scene.place(edit, STONE));
+        // pan far enough to pass through many more chunks than the budget holds
+        for step in 1..10 {
+            let view = camera(step * 400);
+            assert!(view.in_view(scene.z_range).len() <= budget);
+            scene.draw(&view);
+            assert!(scene.chunks.len() <= budget);
+        }
+        // the edited chunk was kept, so the edit is still there, while the others first in view were evicted
+        assert_eq!(scene.material_at(edit), Some(STONE));
+        assert!(first.iter().all(|k| *k == chunk_key(edit) || !scene.chunks.contains_key(k)));
+        assert!(first.len() > 1);
+    }
+
+    #[test]
+    fn zooming_back_and_forth_keeps_focus_under_cursor() {
+        let mut camera = Camera::new([-37, 12], 100, 100, 12.);
+        let (x, y) = (31.3, 58.7);
+        let focus = camera.to_plane(x, y);
+        for i in 0..200 {
+            camera.zoom(if i % 2 == 0 { 1.37 } else { 0.8 }, x, y);
+            // the focus is drawn within half a pixel of the cursor, however many zooms it's been through
+            let [fx, fy] = camera.proj_matrix.proj(focus);
+            assert!((fx - camera.origin[0] as f32 - x).abs() <= 0.5);
+            assert!((fy - camera.origin[1] as f32 - y).abs() <= 0.5);
+        }
+    }
+
+    #[test]
+    fn faces_against_opaque_blocks_are_culled() {
+        let h = Heightmap { data: vec![0.; 16], rows: 4, cols: 4 };
+        let mut scene = Scene::from_heightmap(h, -2, -10, &Climate::generate(4, 4, 0), &BiomeThresholds::new(), None);
+        let rotation = Camera::new([0, 0], 10, 10, 8.).rotation;
+        let block = scene.get([1, 1, 0]).unwrap();
+        // index 0 is half of the top face, and 1 faces along +x into the next column
+        assert!(!scene.face_hidden(&block, 0, rotation));
+        assert!(scene.face_hidden(&block, 1, rotation));
+        assert!(scene.place([1, 1, 1], STONE));
+        assert!(scene.face_hidden(&block, 0, rotation));
+        // faces behind transparent blocks stay visible
+        let glass = scene.materials().id("glass").unwrap();
+        assert!(scene.place([1, 1, 1], glass));
+        assert!(!scene.face_hidden(&block, 0, rotation));
+    }
+}
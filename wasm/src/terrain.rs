@@ -1,20 +1,21 @@
-use rand::{thread_rng, Rng};
+use rand::{Rng, SeedableRng};
+use rand_chacha::ChaCha8Rng;
 use rand_distr::{StandardNormal, Uniform};
 
-use crate::Matrix;
+use crate::{scene::{WORLD_BOTTOM, WORLD_TOP}, Matrix};
 
-fn randn() -> f32 {
-    thread_rng().sample(StandardNormal)
+fn randn(rng: &mut ChaCha8Rng) -> f32 {
+    rng.sample(StandardNormal)
 }
 
-fn random() -> f32 {
-    thread_rng().sample(Uniform::new(0., 1.))
+fn random(rng: &mut ChaCha8Rng) -> f32 {
+    rng.sample(Uniform::new(0., 1.))
 }
 
 fn linspace(start: f32, end: f32, length: usize) -> Vec<f32> {
     let step = (end - start) / ((length - 1) as f32);
     (0..length).scan(0., |state, _| {
-        *state = *state + step;
+        *state += step;
         Some(*state)
     }).collect()
 }
@@ -30,9 +31,9 @@ fn interpolate(x0: f32, x1: f32, w: f32) -> f32 {
 struct Grads(Matrix<(f32, f32)>);
 
 impl Grads {
-    fn new(rows: usize, cols: usize) -> Self {
+    fn new(rows: usize, cols: usize, rng: &mut ChaCha8Rng) -> Self {
         let data = (0..rows * cols).map(
-            |_| (randn(), randn())
+            |_| (randn(rng), randn(rng))
         ).collect();
 
         Self(Matrix::new(data, rows, cols))
@@ -73,12 +74,27 @@ pub struct Heightmap {
     pub cols: usize,
 }
 
-fn perlin(height: usize, width: usize, grad_period: usize) -> Heightmap {
-    let gradcols = 2 + (width / grad_period).max(0);
-    let gradrows = 2 + (height / grad_period).max(0);
-    let grads = Grads::new(gradrows, gradcols);
-    let ys = linspace(1. + random(), (gradrows - 1) as f32 - random(), height);
-    let xs = linspace(1. + random(), (gradcols - 1) as f32 - random(), width);
+impl Heightmap {
+    // fails if the heights don't fit between the lowest and highest blocks a world can hold, leaving room below for
+    // the couple of blocks worlds have under their lowest column; NaN heights are left out and don't count
+    pub fn check_within_world(&self) -> Result<(), String> {
+        let (low, high) = self.range();
+        if low < (WORLD_BOTTOM + 2) as f32 || high > WORLD_TOP as f32 {
+            return Err(format!(
+                "Heights run from {} to {} blocks, past the {} to {} a world can hold",
+                low.floor(), high.ceil(), WORLD_BOTTOM + 2, WORLD_TOP,
+            ));
+        }
+        Ok(())
+    }
+}
+
+fn perlin(height: usize, width: usize, grad_period: usize, rng: &mut ChaCha8Rng) -> Heightmap {
+    let gradcols = 2 + width / grad_period;
+    let gradrows = 2 + height / grad_period;
+    let grads = Grads::new(gradrows, gradcols, rng);
+    let ys = linspace(1. + random(rng), (gradrows - 1) as f32 - random(rng), height);
+    let xs = linspace(1. + random(rng), (gradcols - 1) as f32 - random(rng), width);
 
     let data = (0..height * width).map(
         |idx| {
@@ -90,15 +106,27 @@ fn perlin(height: usize, width: usize, grad_period: usize) -> Heightmap {
     Heightmap { data, rows: height, cols: width }
 }
 
-pub fn perlin_layers(height: usize, width: usize, periods: Vec<usize>, amplitudes: Vec<f32>) -> Heightmap {
+// each layer of `perlin_layers` on its own, scaled by its amplitude, in the order given
+pub fn perlin_octaves(height: usize, width: usize, periods: Vec<usize>, amplitudes: Vec<f32>, seed: u64) -> Vec<Heightmap> {
     assert_eq!(periods.len(), amplitudes.len());
-    periods.into_iter().zip(amplitudes.into_iter()).map(
+    let mut rng = ChaCha8Rng::seed_from_u64(seed);
+    periods.into_iter().zip(amplitudes).map(
         |(period, amplitude)| {
-            let mut h = perlin(height, width, period);
+            let mut h = perlin(height, width, period, &mut rng);
             h.data.iter_mut().for_each(|x| *x *= amplitude);
             h
         }
-    ).reduce(
+    ).collect()
+}
+
+// the same seed always produces the same heightmap, since all layers draw from one seeded generator
+pub fn perlin_layers(height: usize, width: usize, periods: Vec<usize>, amplitudes: Vec<f32>, seed: u64) -> Heightmap {
+    sum_octaves(perlin_octaves(height, width, periods, amplitudes, seed))
+}
+
+// octaves from `perlin_octaves` added together
+pub fn sum_octaves(octaves: Vec<Heightmap>) -> Heightmap {
+    octaves.into_iter().reduce(
         |acc, h| {
             let new_data = acc.data.iter().zip(h.data.iter()).map(
                 |(x_acc, x_new)| x_acc + x_new
@@ -107,3 +135,125 @@ pub fn perlin_layers(height: usize, width: usize, periods: Vec<usize>, amplitude
         }
     ).unwrap()
 }
+
+// mixes a 64-bit value into a well-distributed hash (splitmix64 finalizer)
+fn mix(mut z: u64) -> u64 {
+    z = z.wrapping_add(0x9e3779b97f4a7c15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+    z ^ (z >> 31)
+}
+
+// gradient at an integer lattice point, derived only from the seed and the point's coordinates
+// uses the box-muller transform so gradients are distributed like the ones in `Grads`
+fn lattice_grad(seed: u64, xi: i32, yi: i32) -> (f32, f32) {
+    let h = mix(seed ^ mix(((xi as u32 as u64) << 32) | (yi as u32 as u64)));
+    let u1 = ((h >> 40) as f32 + 1.) / (1u64 << 24) as f32;
+    let u2 = ((h >> 16) & 0xffffff) as f32 / (1u64 << 24) as f32;
+    let r = (-2. * u1.ln()).sqrt();
+    let angle = 2. * std::f32::consts::PI * u2;
+    (r * angle.cos(), r * angle.sin())
+}
+
+// perlin noise that can be sampled at any point in the plane
+// unlike `perlin`, the value at a point doesn't depend on which region is generated, so separately generated areas line up
+fn stable_perlin_at(seed: u64, x: f32, y: f32) -> f32 {
+    let x0 = x.floor() as i32;
+    let y0 = y.floor() as i32;
+    let sx = x - x0 as f32;
+    let sy = y - y0 as f32;
+
+    let dotgrad = |xi: i32, yi: i32| {
+        let (gx, gy) = lattice_grad(seed, xi, yi);
+        (x - xi as f32) * gx + (y - yi as f32) * gy
+    };
+
+    let ix0 = interpolate(dotgrad(x0, y0), dotgrad(x0 + 1, y0), sx);
+    let ix1 = interpolate(dotgrad(x0, y0 + 1), dotgrad(x0 + 1, y0 + 1), sx);
+    interpolate(ix0, ix1, sy)
+}
+
+// coordinate-stable equivalent of `perlin_layers`, for generating unbounded terrain piece by piece
+pub struct NoiseLayers {
+    seed: u64,
+    periods: Vec<usize>,
+    amplitudes: Vec<f32>,
+}
+
+impl NoiseLayers {
+    pub fn new(periods: Vec<usize>, amplitudes: Vec<f32>, seed: u64) -> Self {
+        assert_eq!(periods.len(), amplitudes.len());
+        Self { seed, periods, amplitudes }
+    }
+
+    // rough bound on the magnitude of values produced
+    pub fn max_amplitude(&self) -> f32 {
+        self.amplitudes.iter().map(|a| a.abs()).sum()
+    }
+
+    pub fn at(&self, x: f32, y: f32) -> f32 {
+        self.periods.iter().zip(self.amplitudes.iter()).enumerate().map(
+            |(layer, (period, amplitude))| {
+                let layer_seed = mix(self.seed.wrapping_add(layer as u64));
+                let p = *period as f32;
+                amplitude * stable_perlin_at(layer_seed, x / p, y / p)
+            }
+        ).sum()
+    }
+
+    // heightmap covering rows y0..y0 + rows and columns x0..x0 + cols
+    pub fn heightmap(&self, x0: i32, y0: i32, rows: usize, cols: usize) -> Heightmap {
+        let data = (0..rows * cols).map(
+            |idx| {
+                let (i, j) = (idx / cols, idx % cols);
+                self.at((x0 + j as i32) as f32, (y0 + i as i32) as f32)
+            }
+        ).collect();
+
+        Heightmap { data, rows, cols }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn same_seed_same_heightmap() {
+        let a = perlin_layers(40, 30, vec![10, 4], vec![5., 2.], 42);
+        let b = perlin_layers(40, 30, vec![10, 4], vec![5., 2.], 42);
+        assert_eq!(a.data, b.data);
+        let c = perlin_layers(40, 30, vec![10, 4], vec![5., 2.], 43);
+        assert_ne!(a.data, c.data);
+    }
+
+    #[test]
+    fn octaves_add_up_to_layers() {
+        let octaves = perlin_octaves(20, 30, vec![10, 4], vec![5., 2.], 42);
+        assert_eq!(octaves.len(), 2);
+        assert_eq!(sum_octaves(octaves).data, perlin_layers(20, 30, vec![10, 4], vec![5., 2.], 42).data);
+    }
+
+    #[test]
+    fn heights_must_fit_in_the_world() {
+        let heightmap = |data: Vec<f32>| Heightmap { data, rows: 1, cols: 2 };
+        assert!(heightmap(vec![-1022., 1023.]).check_within_world().is_ok());
+        assert!(heightmap(vec![f32::NAN, 0.]).check_within_world().is_ok());
+        // the bottom leaves room for the blocks under the lowest column
+        assert!(heightmap(vec![-1023., 0.]).check_within_world().is_err());
+        assert!(heightmap(vec![0., 1024.]).check_within_world().is_err());
+        assert!(heightmap(vec![0., f32::INFINITY]).check_within_world().is_err());
+    }
+
+    #[test]
+    fn noise_layers_stitch_across_regions() {
+        let noise = NoiseLayers::new(vec![10, 4], vec![5., 2.], 7);
+        let whole = noise.heightmap(-8, -8, 16, 16);
+        let part = noise.heightmap(0, 0, 8, 8);
+        for i in 0..8 {
+            for j in 0..8 {
+                assert_eq!(whole.data[(i + 8) * 16 + j + 8], part.data[i * 8 + j]);
+            }
+        }
+    }
+}
@@ -0,0 +1,41 @@
+use crate::Color;
+
+// an RGBA image split into square tiles, numbered from the top left along each row
+pub struct Atlas {
+    data: Vec<u8>,
+    width: usize,
+    height: usize,
+    tile_size: usize,
+}
+
+impl Atlas {
+    // nothing if data isn't width * height RGBA pixels, or the image can't hold a single tile
+    // the size is checked for overflow, since usize is only 32 bits on wasm
+    pub fn new(data: Vec<u8>, width: usize, height: usize, tile_size: usize) -> Option<Self> {
+        let len = width.checked_mul(height)?.checked_mul(4)?;
+        if data.len() != len || tile_size == 0 || tile_size > width || tile_size > height {
+            return None;
+        }
+        Some(Self { data, width, height, tile_size })
+    }
+
+    pub fn tile_count(&self) -> usize {
+        (self.width / self.tile_size) * (self.height / self.tile_size)
+    }
+
+    // pixel coordinates of the top left of a tile
+    fn tile_origin(&self, tile: u32) -> [usize; 2] {
+        let per_row = self.width / self.tile_size;
+        let tile = tile as usize;
+        [(tile % per_row) * self.tile_size, (tile / per_row) * self.tile_size]
+    }
+
+    // color of a tile at (s, t), from (0, 0) at its top left to (1, 1) at its bottom right
+    // uses the nearest pixel, and ignores the alpha channel
+    pub fn sample(&self, tile: u32, s: f32, t: f32) -> Color {
+        let [x0, y0] = self.tile_origin(tile);
+        let texel = |c: f32| ((c * self.tile_size as f32).floor().max(0.) as usize).min(self.tile_size - 1);
+        let idx = ((y0 + texel(t)) * self.width + x0 + texel(s)) * 4;
+        Color { r: self.data[idx], g: self.data[idx + 1], b: self.data[idx + 2] }
+    }
+}
@@ -1,88 +1,386 @@
-use std::cmp::Ordering;
+use crate::{Vertex, Color, Canvas, BlendMode, textures::Atlas};
 
-use crate::{Vertex, Color, Canvas};
+// vertices are snapped to 1/256 of a pixel, so that edge functions can be evaluated exactly with integers
+const SUBPIXEL_BITS: u32 = 8;
+const SUBPIXEL: i64 = 1 << SUBPIXEL_BITS;
 
+// coefficients (a, b, c) such that the plane through three (x, y, value) points has value a * x + b * y + c at (x, y)
+// nothing if the points are in a line
+fn plane(vertices: [Vertex; 3], values: [f32; 3]) -> Option<[f32; 3]> {
+    let [[x0, y0], [x1, y1], [x2, y2]] = vertices;
+    let det = (x1 - x0) * (y2 - y0) - (x2 - x0) * (y1 - y0);
+    if det == 0. {
+        return None;
+    }
+    let (d10, d20) = (values[1] - values[0], values[2] - values[0]);
+    let a = (d10 * (y2 - y0) - d20 * (y1 - y0)) / det;
+    let b = (d20 * (x1 - x0) - d10 * (x2 - x0)) / det;
+    Some([a, b, values[0] - a * x0 - b * y0])
+}
 
-pub struct Triangle {
-    vertices: [Vertex; 3],
-    fill: Color,
+fn to_fixed(v: f32) -> i64 {
+    (v * SUBPIXEL as f32).round() as i64
+}
+
+// smallest integer at least a / b, for b > 0
+fn ceil_div(a: i64, b: i64) -> i64 {
+    -(-a).div_euclid(b)
 }
 
-fn draw_horizontal_segment(x0: usize, x1: usize, y: usize, color: &Color, canvas: &mut Canvas) {
-    for x in x0..x1 {
-        canvas.set_pixel(y, x, color)
+// an edge function, which is zero along the edge from a to b and grows toward its inside
+// pixels exactly on the edge belong to it only if it's a top or left edge, so they're drawn by exactly one of two triangles sharing it
+struct Edge {
+    // value at the center of pixel (0, 0), and the changes from one pixel to the next along a row and a column
+    origin: i64,
+    step_x: i64,
+    step_y: i64,
+}
+
+impl Edge {
+    // edges must go around the triangle clockwise on screen, with y pointing down
+    fn new([ax, ay]: [i64; 2], [bx, by]: [i64; 2]) -> Self {
+        let (dx, dy) = (bx - ax, by - ay);
+        let top_left = dy < 0 || (dy == 0 && dx > 0);
+        // pixels on other edges are left out by requiring a value of at least 1 rather than 0
+        let bias = if top_left { 0 } else { -1 };
+        let center = SUBPIXEL / 2;
+        Self {
+            origin: dx * (center - ay) - dy * (center - ax) + bias,
+            step_x: -dy * SUBPIXEL,
+            step_y: dx * SUBPIXEL,
+        }
+    }
+
+    // the pixels of a row on the inside of the edge, as [start, end) clamped to [0, cols)
+    fn span(&self, y: i64, cols: i64) -> (i64, i64) {
+        // inside where value + step_x * x >= 0
+        let value = self.origin + self.step_y * y;
+        match self.step_x.cmp(&0) {
+            std::cmp::Ordering::Greater => (ceil_div(-value, self.step_x).max(0), cols),
+            std::cmp::Ordering::Less => (0, (value.div_euclid(-self.step_x) + 1).min(cols)),
+            std::cmp::Ordering::Equal if value >= 0 => (0, cols),
+            std::cmp::Ordering::Equal => (0, 0),
+        }
     }
 }
 
-impl Triangle {
-    pub fn new(mut vertices: [Vertex; 3], fill: Color) -> Self {
-        // sort vertices in ascending y order
-        vertices.sort_unstable_by(|u, v| u[1].partial_cmp(&v[1]).unwrap_or(Ordering::Equal));
-        Self { vertices, fill }
+// a tile of an atlas mapped onto a triangle
+struct Texture<'a> {
+    atlas: &'a Atlas,
+    tile: u32,
+    // planes giving the point of the tile, from 0 to 1 across and down it, drawn at each point
+    s: [f32; 3],
+    t: [f32; 3],
+}
+
+pub struct Triangle<'a> {
+    vertices: [Vertex; 3],
+    // color at each vertex, blended across the triangle
+    colors: [Color; 3],
+    // plane giving the depth at each point
+    depth: Option<[f32; 3]>,
+    // opacity at each vertex; translucent triangles are blended over the canvas and don't write depth
+    alphas: [f32; 3],
+    // multiplied with the colors when there is one
+    texture: Option<Texture<'a>>,
+    blend: BlendMode,
+}
+
+// the planes giving the barycentric weights of vertices 1 and 2 at each point, for blending between vertices
+type Weights = ([f32; 3], [f32; 3]);
+
+impl<'a> Triangle<'a> {
+    pub fn new(vertices: [Vertex; 3], fill: Color) -> Self {
+        let colors = [fill.clone(), fill.clone(), fill];
+        Self { vertices, colors, depth: None, alphas: [1.; 3], texture: None, blend: BlendMode::SourceOver }
     }
 
-    fn draw_flat_bottom(&self, canvas: &mut Canvas) {
-        // draw under the assumption that vertices 1 and 2 are at equal y value
-        let [v0, v1_, v2_] = self.vertices;
-        let (v1, v2) = if v1_[0] < v2_[0] {
-            (v1_, v2_)
-        } else {
-            (v2_, v1_)
-        };
-        let invslope0 = (v1[0] - v0[0]) / (v1[1] - v0[1]);
-        let invslope1 = (v2[0] - v0[0]) / (v2[1] - v0[1]);
-        let mut curx0 = v0[0];
-        let mut curx1 = v0[0];
-        for scanline_y in (v0[1] as i32)..=(v1[1] as i32) {
-            // draw line between curx0 and curx1 at current scanline
-            if scanline_y >= 0 {
-                draw_horizontal_segment(curx0 as usize, curx1 as usize, scanline_y as usize, &self.fill, canvas);
+    // maps a tile of the atlas onto the triangle, given where each vertex falls on the tile
+    // mapping is affine, which is exact since the projection is orthographic
+    pub fn textured(mut self, atlas: &'a Atlas, tile: u32, coords: [[f32; 2]; 3]) -> Self {
+        let s = plane(self.vertices, coords.map(|c| c[0]));
+        let t = plane(self.vertices, coords.map(|c| c[1]));
+        if let (Some(s), Some(t)) = (s, t) {
+            self.texture = Some(Texture { atlas, tile, s, t });
+        }
+        self
+    }
+
+    // gives each vertex its own color
+    pub fn with_colors(mut self, colors: [Color; 3]) -> Self {
+        self.colors = colors;
+        self
+    }
+
+    pub fn translucent(self, alpha: f32) -> Self {
+        self.with_alphas([alpha; 3])
+    }
+
+    // gives each vertex its own opacity
+    pub fn with_alphas(mut self, alphas: [f32; 3]) -> Self {
+        self.alphas = alphas;
+        self
+    }
+
+    // how the triangle combines with what's already on the canvas
+    pub fn blended(mut self, blend: BlendMode) -> Self {
+        self.blend = blend;
+        self
+    }
+
+    // whether the triangle hides what's behind it, so it can overwrite pixels and write depth
+    fn opaque(&self) -> bool {
+        self.blend == BlendMode::SourceOver && self.alphas.iter().all(|a| *a >= 1.)
+    }
+
+    // weights for blending between vertices, or nothing if every vertex looks the same
+    fn weights(&self) -> Option<Weights> {
+        let [c0, c1, c2] = &self.colors;
+        let [a0, a1, a2] = self.alphas;
+        if c0 == c1 && c1 == c2 && a0 == a1 && a1 == a2 {
+            return None;
+        }
+        Some((plane(self.vertices, [0., 1., 0.])?, plane(self.vertices, [0., 0., 1.])?))
+    }
+
+    fn draw_horizontal_segment(&self, x0: usize, x1: usize, y: usize, weights: Option<&Weights>, canvas: &mut Canvas) {
+        let opaque = self.opaque();
+        // planes are sampled at pixel centers
+        let at = |[a, b, c]: [f32; 3], x: usize| a * (x as f32 + 0.5) + b * (y as f32 + 0.5) + c;
+        for x in x0..x1 {
+            if let Some(depth) = self.depth {
+                if !canvas.depth_test(y, x, at(depth, x), opaque) {
+                    continue;
+                }
+            }
+            let (color, alpha) = match weights {
+                Some(&(p1, p2)) => {
+                    let (w1, w2) = (at(p1, x), at(p2, x));
+                    let mix = |[a, b, c]: [f32; 3]| (1. - w1 - w2) * a + w1 * b + w2 * c;
+                    let [c0, c1, c2] = &self.colors;
+                    let channel = |a: u8, b: u8, c: u8| mix([a as f32, b as f32, c as f32]).round().clamp(0., 255.) as u8;
+                    let color = Color { r: channel(c0.r, c1.r, c2.r), g: channel(c0.g, c1.g, c2.g), b: channel(c0.b, c1.b, c2.b) };
+                    (color, mix(self.alphas).clamp(0., 1.))
+                }
+                None => (self.colors[0].clone(), self.alphas[0]),
+            };
+            let color = match &self.texture {
+                Some(texture) => texture.atlas.sample(texture.tile, at(texture.s, x), at(texture.t, x)).modulated(&color),
+                None => color,
+            };
+            if opaque {
+                canvas.set_pixel(y, x, &color);
+            } else {
+                canvas.blend_pixel(y, x, &color, alpha, self.blend);
+            }
+        }
+    }
+
+    // a triangle whose pixels are depth tested, given the depth at each vertex
+    pub fn with_depth(vertices: [Vertex; 3], depths: [f32; 3], fill: Color) -> Self {
+        Self { depth: plane(vertices, depths), ..Self::new(vertices, fill) }
+    }
+
+    // fills the pixels whose centers lie inside the triangle, following the top-left rule for centers on an edge
+    // triangles sharing an edge cover each pixel along it exactly once, at any scale
+    pub fn draw(&self, canvas: &mut Canvas) {
+        let [v0, mut v1, mut v2] = self.vertices.map(|v| v.map(to_fixed));
+        let area = (v1[0] - v0[0]) * (v2[1] - v0[1]) - (v1[1] - v0[1]) * (v2[0] - v0[0]);
+        if area == 0 {
+            return;
+        }
+        if area < 0 {
+            std::mem::swap(&mut v1, &mut v2);
+        }
+        let edges = [Edge::new(v0, v1), Edge::new(v1, v2), Edge::new(v2, v0)];
+        let weights = self.weights();
+        // rows whose centers might be inside
+        let top = [v0, v1, v2].iter().map(|v| v[1]).min().unwrap_or(0);
+        let bottom = [v0, v1, v2].iter().map(|v| v[1]).max().unwrap_or(0);
+        let first = (top.div_euclid(SUBPIXEL) - 1).max(0);
+        let last = (bottom.div_euclid(SUBPIXEL) + 1).min(canvas.rows as i64 - 1);
+        let cols = canvas.cols as i64;
+        for y in first..=last {
+            let (x0, x1) = edges.iter()
+                .map(|edge| edge.span(y, cols))
+                .fold((0, cols), |(start, end), (s, e)| (start.max(s), end.min(e)));
+            if x0 < x1 {
+                self.draw_horizontal_segment(x0 as usize, x1 as usize, y as usize, weights.as_ref(), canvas);
             }
-            // advance curx0 and curx1
-            curx0 += invslope0;
-            curx1 += invslope1;
         }
     }
-    fn draw_flat_top(&self, canvas: &mut Canvas) {
-        // draw under the assumption that vertices 1 and 2 are at equal y value
-        let [v0_, v1_, v2] = self.vertices;
-        let (v0, v1) = if v0_[0] < v1_[0] {
-            (v0_, v1_)
-        } else {
-            (v1_, v0_)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pixel(canvas: &Canvas, i: usize, j: usize) -> &[u8] {
+        let idx = (i * canvas.cols + j) * 4;
+        &canvas.data[idx..idx + 4]
+    }
+
+    #[test]
+    fn depth_buffer_keeps_nearest_triangle() {
+        let mut canvas = Canvas::with_depth(16, 16);
+        let vertices = [[0., 0.], [16., 0.], [0., 16.]];
+        // larger depths are nearer; the nearer triangle is drawn first and stays in front
+        Triangle::with_depth(vertices, [2.; 3], Color::from_hex(0xff0000)).draw(&mut canvas);
+        Triangle::with_depth(vertices, [1.; 3], Color::from_hex(0x0000ff)).draw(&mut canvas);
+        assert_eq!(pixel(&canvas, 2, 2), [255, 0, 0, 255]);
+        // while one nearer still covers it
+        Triangle::with_depth(vertices, [3.; 3], Color::from_hex(0x00ff00)).draw(&mut canvas);
+        assert_eq!(pixel(&canvas, 2, 2), [0, 255, 0, 255]);
+        // depths are interpolated, so triangles that cross are each in front where they're nearer
+        let mut canvas = Canvas::with_depth(16, 16);
+        Triangle::with_depth(vertices, [0., 2., 0.], Color::from_hex(0xff0000)).draw(&mut canvas);
+        Triangle::with_depth(vertices, [2., 0., 2.], Color::from_hex(0x0000ff)).draw(&mut canvas);
+        assert_eq!(pixel(&canvas, 1, 12)[..3], [255, 0, 0]);
+        assert_eq!(pixel(&canvas, 12, 1)[..3], [0, 0, 255]);
+    }
+
+    #[test]
+    fn blends_vertex_colors() {
+        let mut canvas = Canvas::new(64, 64);
+        let colors = [Color::from_hex(0xff0000), Color::from_hex(0x00ff00), Color::from_hex(0x0000ff)];
+        Triangle::new([[0., 0.], [64., 0.], [0., 64.]], Color::from_hex(0))
+            .with_colors(colors)
+            .draw(&mut canvas);
+        // mostly the color of the nearest vertex
+        assert!(pixel(&canvas, 0, 0)[0] > 240);
+        assert!(pixel(&canvas, 0, 62)[1] > 230);
+        assert!(pixel(&canvas, 62, 0)[2] > 230);
+        // and an even mix toward the middle
+        let middle = pixel(&canvas, 21, 21);
+        assert!(middle[..3].iter().all(|c| (80..=90).contains(c)));
+    }
+
+    #[test]
+    fn blends_vertex_alphas() {
+        let mut canvas = Canvas::new(64, 64);
+        Triangle::new([[0., 0.], [64., 0.], [0., 64.]], Color::from_hex(0xffffff))
+            .with_alphas([1., 0., 0.])
+            .draw(&mut canvas);
+        // alpha goes from opaque at the first vertex to clear at the others
+        assert!(pixel(&canvas, 0, 0)[3] > 240);
+        assert!(pixel(&canvas, 0, 62)[3] < 15);
+        assert!(pixel(&canvas, 21, 21)[3] > 70 && pixel(&canvas, 21, 21)[3] < 100);
+    }
+
+    #[test]
+    fn blend_modes_combine_with_canvas() {
+        let full = [[0., 0.], [64., 0.], [0., 64.]];
+        let over = |mode: BlendMode, alpha: f32| {
+            let mut canvas = Canvas::new(64, 64);
+            Triangle::new(full, Color::from_hex(0x804020)).draw(&mut canvas);
+            Triangle::new(full, Color::from_hex(0x8080ff)).translucent(alpha).blended(mode).draw(&mut canvas);
+            pixel(&canvas, 10, 10).to_vec()
         };
-        let invslope0 = (v2[0] - v0[0]) / (v2[1] - v0[1]);
-        let invslope1 = (v2[0] - v1[0]) / (v2[1] - v1[1]);
-        let mut curx0 = v2[0];
-        let mut curx1 = v2[0];
-        for scanline_y in ((v0[1] as i32)..=(v2[1] as i32)).rev() {
-            // draw line between curx0 and curx1 at current scanline
-            if scanline_y >= 0 {
-                draw_horizontal_segment(curx0 as usize, curx1 as usize, scanline_y as usize, &self.fill, canvas);
+        assert_eq!(over(BlendMode::SourceOver, 0.5), [0x80, 0x60, 0x90, 255]);
+        assert_eq!(over(BlendMode::Additive, 1.), [0xff, 0xc0, 0xff, 255]);
+        assert_eq!(over(BlendMode::Multiply, 1.), [0x40, 0x20, 0x20, 255]);
+        // over nothing, each mode just draws the color
+        let mut canvas = Canvas::new(64, 64);
+        Triangle::new(full, Color::from_hex(0x8080ff)).blended(BlendMode::Multiply).draw(&mut canvas);
+        assert_eq!(pixel(&canvas, 10, 10), [0x80, 0x80, 0xff, 255]);
+    }
+
+    // how many of the triangles cover each pixel of a canvas
+    fn coverage(triangles: &[[Vertex; 3]], rows: usize, cols: usize) -> Vec<u32> {
+        let mut counts = vec![0; rows * cols];
+        for &vertices in triangles {
+            let mut canvas = Canvas::new(rows, cols);
+            Triangle::new(vertices, Color::from_hex(0xffffff)).draw(&mut canvas);
+            for (count, pixel) in counts.iter_mut().zip(canvas.data.chunks(4)) {
+                *count += (pixel[3] > 0) as u32;
             }
-            // advance curx0 and curx1
-            curx0 -= invslope0;
-            curx1 -= invslope1;
         }
+        counts
     }
-    pub fn draw(&self, canvas: &mut Canvas) {
-        let [v0, v1, v2] = self.vertices;
-        // handle trivial case of flat-bottom triangle
-        if v1[1] == v2[1] {
-            self.draw_flat_bottom(canvas)
+
+    // two triangles for each cell of a lattice with the given axes, split along either diagonal, from -n to n cells out
+    fn lattice(origin: Vertex, a: Vertex, b: Vertex, n: i32, split: bool) -> Vec<[Vertex; 3]> {
+        let point = |i: i32, j: i32| [
+            origin[0] + i as f32 * a[0] + j as f32 * b[0],
+            origin[1] + i as f32 * a[1] + j as f32 * b[1],
+        ];
+        let mut triangles = Vec::new();
+        for i in -n..n {
+            for j in -n..n {
+                let [p00, p10, p01, p11] = [point(i, j), point(i + 1, j), point(i, j + 1), point(i + 1, j + 1)];
+                if split {
+                    triangles.extend([[p00, p10, p11], [p00, p11, p01]]);
+                } else {
+                    triangles.extend([[p00, p10, p01], [p10, p11, p01]]);
+                }
+            }
         }
-        // handle trivial case of flat-top triangle
-        else if v0[1] == v1[1] {
-            self.draw_flat_top(canvas)
+        triangles
+    }
+
+    #[test]
+    fn square_tiles_cover_every_pixel_once() {
+        for scale in [1., 2., 3.7, 8., 12.5] {
+            for offset in [[0., 0.], [0.5, 0.5], [0.25, 0.75], [0.3, 0.1]] {
+                let triangles = lattice(offset, [scale, 0.], [0., scale], (40. / scale) as i32 + 2, true);
+                assert!(coverage(&triangles, 40, 40).iter().all(|&c| c == 1), "scale {} offset {:?}", scale, offset);
+            }
         }
-        // split triangle into top-flat and bottom-flat sections
-        else {
-            let v3 = [
-                v0[0] + ((v2[0] - v0[0]) * (v1[1] - v0[1]) / (v2[1] - v0[1])),
-                v1[1],
-            ];
-            Triangle { vertices: [v0, v1, v3], fill: self.fill.clone() }.draw_flat_bottom(canvas);
-            Triangle { vertices: [v1, v3, v2], fill: self.fill.clone() }.draw_flat_top(canvas);
+    }
+
+    #[test]
+    fn isometric_tiles_cover_every_pixel_once() {
+        // the slices blocks are drawn with, at scales blocks are drawn at
+        let (cos, sin) = (std::f32::consts::FRAC_PI_6.cos(), std::f32::consts::FRAC_PI_6.sin());
+        for scale in [2., 5., 6.25, 12., 17.3] {
+            for split in [false, true] {
+                let triangles = lattice([20.3, 17.9], [scale * cos, scale * sin], [-scale * cos, scale * sin], (40. / scale) as i32 + 2, split);
+                assert!(coverage(&triangles, 40, 40).iter().all(|&c| c == 1), "scale {} split {}", scale, split);
+            }
         }
     }
+
+    #[test]
+    fn triangle_fans_cover_every_pixel_once() {
+        // triangles around a point, out to corners of a square past the canvas, with edges at many slopes
+        let center = [13.37, 21.5];
+        let mut corners: Vec<Vertex> = (0..12).map(|i| [-10. + i as f32 * 5.17, -10.]).collect();
+        corners.extend((0..12).map(|i| [50., -10. + i as f32 * 4.9]));
+        corners.extend((0..12).map(|i| [50. - i as f32 * 5.03, 50.]));
+        corners.extend((0..12).map(|i| [-10., 50. - i as f32 * 5.]));
+        let triangles: Vec<[Vertex; 3]> = (0..corners.len())
+            .map(|i| [center, corners[i], corners[(i + 1) % corners.len()]])
+            .collect();
+        assert!(coverage(&triangles, 40, 40).iter().all(|&c| c == 1));
+    }
+
+    #[test]
+    fn winding_and_degenerate_triangles() {
+        // either winding covers the same pixels
+        let clockwise = coverage(&[[[1.5, 2.2], [30.1, 9.7], [8.4, 35.]]], 40, 40);
+        let counter = coverage(&[[[1.5, 2.2], [8.4, 35.], [30.1, 9.7]]], 40, 40);
+        assert_eq!(clockwise, counter);
+        assert!(clockwise.contains(&1));
+        // and triangles with no area cover nothing
+        assert!(coverage(&[[[1., 1.], [20., 20.], [39., 39.]]], 40, 40).iter().all(|&c| c == 0));
+    }
+
+    #[test]
+    fn maps_texture_tiles() {
+        // two 2x2 tiles side by side, the first red on the left and blue on the right
+        let (red, blue, green) = ([255, 0, 0, 255], [0, 0, 255, 255], [0, 255, 0, 255]);
+        let rows = [[red, blue, green, green], [red, blue, green, green]];
+        let atlas = Atlas::new(rows.concat().concat(), 4, 2, 2).unwrap();
+        let mut canvas = Canvas::new(64, 64);
+        Triangle::new([[0., 0.], [64., 0.], [0., 64.]], Color::from_hex(0xffffff))
+            .textured(&atlas, 0, [[0., 0.], [1., 0.], [0., 1.]])
+            .draw(&mut canvas);
+        assert_eq!(pixel(&canvas, 10, 5), red);
+        assert_eq!(pixel(&canvas, 5, 40), blue);
+        // texels are multiplied by the triangle's color
+        Triangle::new([[0., 0.], [64., 0.], [0., 64.]], Color::from_hex(0x808080))
+            .textured(&atlas, 1, [[0., 0.], [1., 0.], [0., 1.]])
+            .draw(&mut canvas);
+        assert_eq!(pixel(&canvas, 10, 5), [0, 128, 0, 255]);
+    }
 }
@@ -0,0 +1,61 @@
+use std::{cmp::Reverse, collections::BinaryHeap};
+
+// height of the water surface over each cell of a grid of column tops
+// cells whose water surface isn't above their top are dry
+// water flows in from past the edges of the grid at sea_level, and fills any enclosed basin up to the point where it
+// would spill over, so lakes can sit above sea level and low ground shielded from the sea by higher ground stays a lake
+// at its own level rather than at sea level
+pub fn water_levels(tops: &[i32], rows: usize, cols: usize, sea_level: i32) -> Vec<i32> {
+    assert_eq!(tops.len(), rows * cols);
+    // priority flood: visit cells from lowest to highest water level, starting at the edges
+    let mut levels = vec![i32::MIN; rows * cols];
+    let mut queue = BinaryHeap::new();
+    for idx in 0..rows * cols {
+        let (i, j) = (idx / cols, idx % cols);
+        if i == 0 || j == 0 || i == rows - 1 || j == cols - 1 {
+            levels[idx] = tops[idx].max(sea_level);
+            queue.push(Reverse((levels[idx], idx)));
+        }
+    }
+    while let Some(Reverse((level, idx))) = queue.pop() {
+        let (i, j) = (idx / cols, idx % cols);
+        let neighbors = [
+            (i > 0).then(|| idx - cols),
+            (i + 1 < rows).then(|| idx + cols),
+            (j > 0).then(|| idx - 1),
+            (j + 1 < cols).then(|| idx + 1),
+        ];
+        for &n in neighbors.iter().flatten() {
+            if levels[n] == i32::MIN {
+                // water can't drain out of n any lower than the level of the cell it was reached from
+                levels[n] = tops[n].max(level);
+                queue.push(Reverse((levels[n], n)));
+            }
+        }
+    }
+
+    levels
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fills_basins_to_spill_height() {
+        // a basin enclosed by a rim of height 3, with a gap down to 2
+        #[rustfmt::skip]
+        let tops = vec![
+            3, 3, 3, 3, 3,
+            3, 0, 1, 0, 3,
+            3, 0, 0, 0, 2,
+            3, 3, 3, 3, 3,
+        ];
+        let levels = water_levels(&tops, 4, 5, -5);
+        assert_eq!(&levels[5..10], &[3, 2, 2, 2, 3]);
+        assert_eq!(&levels[10..15], &[3, 2, 2, 2, 2]);
+        // a sea above the rim covers everything
+        let levels = water_levels(&tops, 4, 5, 4);
+        assert!(levels.iter().all(|l| *l == 4));
+    }
+}
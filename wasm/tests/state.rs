@@ -0,0 +1,449 @@
+//! Tests of the `StateManager` API that can run natively.
+
+use std::convert::TryInto;
+
+use wasm::{
+    heightmap_png, hillshade_png, BiomeThresholds, BlendMode, ElevationParams, ErosionParams, Face, Light, NoData,
+    StateManager,
+};
+
+fn flat_state() -> StateManager {
+    StateManager::new(20, 20, vec![10], vec![0.], 100, 100, 8., 0, -10, &BiomeThresholds::new(), &ErosionParams::new())
+}
+
+#[test]
+fn place_and_remove_blocks() {
+    let mut state = flat_state();
+    assert_eq!(state.column_height(3, 4), Some(0));
+    let stone = state.material_id("stone").unwrap();
+    assert!(state.place_block(3, 4, 5, stone));
+    assert_eq!(state.get_block(3, 4, 5), Some(stone));
+    assert_eq!(state.column_height(3, 4), Some(5));
+    assert!(state.remove_block(3, 4, 5));
+    assert!(!state.remove_block(3, 4, 5));
+    assert_eq!(state.get_block(3, 4, 5), None);
+    assert_eq!(state.column_height(3, 4), Some(0));
+    // unknown materials and air can't be placed
+    assert!(!state.place_block(3, 4, 5, 200));
+    assert!(!state.place_block(3, 4, 5, state.material_id("air").unwrap()));
+    // blocks far above or below the world would need huge columns
+    assert!(!state.place_block(3, 4, 2_000_000_000, stone));
+    assert!(!state.place_block(3, 4, -2_000_000_000, stone));
+    assert!(state.place_block(3, 4, 1023, stone));
+    assert!(!state.place_block(3, 4, 1024, stone));
+    assert_eq!(state.get_block(3, 4, 5), None);
+}
+
+#[test]
+fn water_level_stays_within_the_world() {
+    let biomes = BiomeThresholds::new();
+    for water_level in [5000, 1_000_000_000] {
+        let state = StateManager::new(4, 4, vec![4], vec![1.], 10, 10, 4., 0, water_level, &biomes, &ErosionParams::new());
+        assert_eq!(state.column_height(0, 0), Some(1023));
+    }
+    let state = StateManager::new(4, 4, vec![4], vec![1.], 10, 10, 4., 0, i32::MIN, &biomes, &ErosionParams::new());
+    assert!(state.column_height(0, 0).is_some());
+    // imported worlds too
+    let file = greyscale_png(&[0, 255, 0, 255], 2, 2);
+    let state = StateManager::from_heightmap_png(&file, 4., 0., 10, 10, 4., 0, 5000, &biomes).unwrap();
+    assert_eq!(state.column_height(0, 0), Some(1023));
+}
+
+#[test]
+fn custom_materials() {
+    let mut state = flat_state();
+    let crystal = state.add_material("crystal", 0xc8e6f0, 1., 1., 1., true, false, false).unwrap();
+    assert_eq!(state.material_id("crystal"), Some(crystal));
+    assert_eq!(state.material_name(crystal).as_deref(), Some("crystal"));
+    assert!(state.material_flags(crystal).unwrap().transparent);
+    assert!(state.place_block(1, 1, 1, crystal));
+    assert_eq!(state.get_block(1, 1, 1), Some(crystal));
+    // names must be unique, since that's how materials are looked up
+    assert_eq!(state.add_material("crystal", 0xffffff, 1., 1., 1., false, false, false), None);
+    assert_eq!(state.add_material("stone", 0xffffff, 1., 1., 1., false, false, false), None);
+    assert_eq!(state.material_id("crystal"), Some(crystal));
+}
+
+#[test]
+fn material_shading_scales_the_light() {
+    let mut state = flat_state();
+    state.shift(-50, 0);
+    let plain = state.add_material("plain", 0x808080, 1., 1., 1., false, false, false).unwrap();
+    let dim = state.add_material("dim", 0x808080, 0.5, 1., 1., false, false, false).unwrap();
+    state.place_block(5, 5, 1, plain);
+    state.place_block(7, 3, 1, dim);
+    state.draw();
+    let (plain, dim) = (top_color(&state, 5, 5, 1), top_color(&state, 7, 3, 1));
+    for c in 0..3 {
+        assert!(dim[c] < plain[c], "{:?} should be darker than {:?}", dim, plain);
+    }
+}
+
+#[test]
+fn pick_finds_frontmost_block() {
+    let mut state = flat_state();
+    state.shift(-50, 0);
+    // the top of (5, 5, 0) is centered on screen x = 0, y = 44
+    let pick = state.pick(50, 44).unwrap();
+    assert_eq!((pick.x, pick.y, pick.z, pick.face), (5, 5, 0, Face::Top));
+    // a block one up and one diagonally forward covers the same spot
+    state.place_block(6, 6, 1, 1);
+    let pick = state.pick(50, 44).unwrap();
+    assert_eq!((pick.x, pick.y, pick.z, pick.face), (6, 6, 1, Face::Top));
+    // a block directly above shows its right face just right of that point
+    state.remove_block(6, 6, 1);
+    state.place_block(5, 5, 1, 1);
+    let pick = state.pick(52, 44).unwrap();
+    assert_eq!((pick.x, pick.y, pick.z, pick.face), (5, 5, 1, Face::Right));
+    assert!(state.pick(0, 0).is_none());
+}
+
+#[test]
+fn rotation_keeps_center_in_place() {
+    let mut state = flat_state();
+    state.shift(-50, 0);
+    let center = state.pick(50, 50).unwrap();
+    for _ in 0..4 {
+        state.rotate(true);
+        let pick = state.pick(50, 50).unwrap();
+        assert_eq!((pick.x, pick.y, pick.z), (center.x, center.y, center.z));
+    }
+    // after a clockwise turn, the faces drawn on the right are the world's -y faces
+    state.rotate(true);
+    state.place_block(center.x, center.y, 1, 1);
+    let pick = (40..60).flat_map(|px| (30..50).map(move |py| (px, py)))
+        .filter_map(|(px, py)| state.pick(px, py))
+        .find(|pick| pick.z == 1 && pick.face == Face::Right)
+        .unwrap();
+    assert_eq!((pick.nx, pick.ny, pick.nz), (0, -1, 0));
+    assert_eq!(state.get_block(pick.x + pick.nx, pick.y + pick.ny, pick.z + pick.nz), None);
+    // shifting after a turn redraws the whole canvas rather than keeping pixels from before it
+    // faces that share an edge can be drawn in either order, so a couple of edge pixels may differ between draws
+    state.draw();
+    state.rotate(false);
+    state.shift_x(5);
+    let shifted = state.get_canvas().0;
+    state.draw();
+    let redrawn = state.get_canvas().0;
+    assert!(shifted.chunks(4).zip(redrawn.chunks(4)).filter(|(a, b)| a != b).count() < 20);
+}
+
+#[test]
+fn zoom_keeps_focus_in_place() {
+    let mut state = flat_state();
+    state.shift(-50, 0);
+    let focus = state.pick(30, 60).unwrap();
+    for factor in [1.37, 0.6, 2.5] {
+        state.zoom(factor, 30.5, 60.5);
+        let pick = state.pick(30, 60).unwrap();
+        assert_eq!((pick.x, pick.y, pick.z), (focus.x, focus.y, focus.z));
+    }
+    // scale stays within limits
+    assert_eq!(state.zoom(1000., 30.5, 60.5), 64.);
+    assert_eq!(state.zoom(1e-6, 30.5, 60.5), 2.);
+    // and factors that would make it meaningless are ignored
+    for factor in [f32::NAN, f32::INFINITY, 0., -2.] {
+        assert_eq!(state.zoom(factor, 30.5, 60.5), 2.);
+    }
+}
+
+// colors of the pixels showing the top of block (x, y, z)
+fn top_colors(state: &StateManager, x: i32, y: i32, z: i32) -> Vec<[u8; 4]> {
+    let canvas = state.get_canvas().0;
+    (0..100).flat_map(|px| (0..100).map(move |py| (px, py)))
+        .filter(|&(px, py)| state.pick(px, py).is_some_and(|p| (p.x, p.y, p.z, p.face) == (x, y, z, Face::Top)))
+        .map(|(px, py)| canvas[(py * 100 + px) * 4..][..4].try_into().unwrap())
+        .collect()
+}
+
+// color of some pixel showing the top of block (x, y, z)
+fn top_color(state: &StateManager, x: i32, y: i32, z: i32) -> [u8; 4] {
+    top_colors(state, x, y, z)[0]
+}
+
+#[test]
+fn columns_cast_shadows() {
+    let mut state = flat_state();
+    state.shift(-50, 0);
+    // the sun is 45 degrees up toward +x
+    let mut light = Light::new();
+    (light.sun_x, light.sun_y, light.sun_z) = (1., 0., 1.);
+    state.set_light(&light);
+    state.draw();
+    let stone = state.material_id("stone").unwrap();
+    for z in 1..=4 {
+        state.place_block(10, 10, z, stone);
+    }
+    state.draw();
+    // a four block pillar shades the four columns behind it
+    let lit = top_color(&state, 8, 14, 0);
+    assert_ne!(top_color(&state, 8, 10, 0), lit);
+    assert_eq!(top_color(&state, 5, 10, 0), lit);
+    // and the shadow goes away with the pillar
+    for z in 1..=4 {
+        state.remove_block(10, 10, z);
+    }
+    state.draw();
+    assert_eq!(top_color(&state, 8, 10, 0), lit);
+}
+
+#[test]
+fn ambient_occlusion_darkens_crowded_corners() {
+    let mut state = flat_state();
+    state.shift(-50, 0);
+    state.place_block(10, 10, 1, state.material_id("stone").unwrap());
+    state.draw();
+    // the ground next to the block gets darker toward it
+    let mut colors = top_colors(&state, 9, 10, 0);
+    colors.dedup();
+    assert!(colors.len() > 1);
+    state.set_ambient_occlusion(false);
+    state.draw();
+    let mut colors = top_colors(&state, 9, 10, 0);
+    colors.dedup();
+    assert_eq!(colors.len(), 1);
+}
+
+#[test]
+fn textured_faces_use_atlas_tiles() {
+    let mut state = flat_state();
+    state.shift(-50, 0);
+    // the atlas must hold width * height RGBA pixels
+    assert!(!state.set_texture_atlas(vec![0; 12], 2, 2, 1));
+    assert!(!state.set_texture_atlas(vec![0; 16], 2, 2, 3));
+    // sizes whose pixel count overflows are rejected rather than wrapping around to match the data
+    assert!(!state.set_texture_atlas(vec![0; 4], usize::MAX / 4 + 2, 1, 1));
+    // a red tile then a white one
+    assert!(state.set_texture_atlas(vec![255, 0, 0, 255, 255, 255, 255, 255], 2, 1, 1));
+    let ground = state.get_block(10, 10, 0).unwrap();
+    state.draw();
+    let flat = top_color(&state, 10, 10, 0);
+    assert!(state.set_material_tiles(ground, 0, 1, 1));
+    state.draw();
+    let [r, g, b, _] = top_color(&state, 10, 10, 0);
+    assert!(r > 0 && g == 0 && b == 0);
+    // tiles outside the atlas fall back to the flat color
+    assert!(state.set_material_tiles(ground, 5, 1, 1));
+    state.draw();
+    assert_eq!(top_color(&state, 10, 10, 0), flat);
+    assert!(state.clear_material_tiles(ground));
+    assert!(!state.set_material_tiles(200, 0, 0, 0));
+}
+
+#[test]
+fn antialiasing_smooths_edges() {
+    let mut state = flat_state();
+    state.shift(-50, 0);
+    let edge_pixels = |state: &StateManager| {
+        state.get_canvas().0.chunks(4).filter(|p| p[3] > 0 && p[3] < 255).count()
+    };
+    state.draw();
+    assert_eq!(edge_pixels(&state), 0);
+    // the cost is the number of pixels drawn per canvas pixel
+    assert_eq!(state.set_antialiasing(3), 9);
+    assert_eq!(state.set_antialiasing(10), 16);
+    assert_eq!(state.set_antialiasing(2), 4);
+    state.draw();
+    // the world's outline is partly covered, and blends with whatever the canvas is drawn over
+    assert!(edge_pixels(&state) > 0);
+    // shifting only draws the new part, which lines up with the rest
+    state.shift_x(7);
+    state.shift_y(-5);
+    let shifted = state.get_canvas().0;
+    state.draw();
+    assert!(shifted == state.get_canvas().0);
+    assert_eq!(state.set_antialiasing(0), 1);
+}
+
+#[test]
+fn translucent_materials_show_what_is_behind() {
+    let mut state = flat_state();
+    state.shift(-50, 0);
+    state.draw();
+    let ground = top_color(&state, 10, 10, 0);
+    let blocks = ["glass", "ice", "water"].map(|name| state.material_id(name).unwrap());
+    let mut colors = Vec::new();
+    for block in blocks {
+        state.place_block(10, 10, 1, block);
+        state.draw();
+        colors.push(top_color(&state, 10, 10, 1));
+        state.remove_block(10, 10, 1);
+    }
+    // each covers the ground without hiding it
+    assert!(colors.iter().all(|c| *c != ground));
+    // with stone seen through them instead of the ground they look different
+    state.remove_block(9, 9, 0);
+    state.place_block(9, 9, 0, state.material_id("stone").unwrap());
+    for (block, color) in blocks.iter().zip(&colors) {
+        state.place_block(10, 10, 1, *block);
+        state.draw();
+        assert_ne!(top_color(&state, 10, 10, 1), *color);
+        state.remove_block(10, 10, 1);
+    }
+    // and blending can be changed per material, making glass hide what's behind it
+    let glass = blocks[0];
+    assert!(state.set_material_blend(glass, BlendMode::SourceOver));
+    state.place_block(10, 10, 1, glass);
+    state.draw();
+    let opaque = top_color(&state, 10, 10, 1);
+    state.remove_block(9, 9, 0);
+    state.draw();
+    assert_eq!(top_color(&state, 10, 10, 1), opaque);
+}
+
+#[test]
+fn canvas_exports_as_png() {
+    let mut state = flat_state();
+    state.shift(-50, 0);
+    state.draw();
+    let png = state.get_png().unwrap();
+    assert_eq!(&png[..8], b"\x89PNG\r\n\x1a\n");
+    // width and height are the first fields of the header chunk
+    assert_eq!(png[16..24], [0, 0, 0, 100, 0, 0, 0, 100]);
+    let thumbnail = state.get_thumbnail_png(4).unwrap();
+    assert_eq!(thumbnail[16..24], [0, 0, 0, 25, 0, 0, 0, 25]);
+    // factors past the canvas' side shrink it to a single pixel rather than nothing
+    for factor in [100, 1000, usize::MAX] {
+        let thumbnail = state.get_thumbnail_png(factor).unwrap();
+        assert_eq!(thumbnail[16..24], [0, 0, 0, 1, 0, 0, 0, 1]);
+    }
+    // an empty canvas can't be saved, but doesn't panic
+    let empty = StateManager::new(20, 20, vec![10], vec![0.], 0, 0, 8., 0, -10, &BiomeThresholds::new(), &ErosionParams::new());
+    assert!(empty.get_png().is_err());
+    assert!(empty.get_thumbnail_png(4).is_err());
+}
+
+// an 8 bit greyscale PNG file
+fn greyscale_png(data: &[u8], width: u32, height: u32) -> Vec<u8> {
+    let mut file = Vec::new();
+    let mut encoder = png::Encoder::new(&mut file, width, height);
+    encoder.set_color(png::ColorType::Grayscale);
+    encoder.write_header().unwrap().write_image_data(data).unwrap();
+    file
+}
+
+#[test]
+fn worlds_from_heightmap_images() {
+    // a ramp rising along each row
+    let data: Vec<u8> = (0..16 * 16).map(|i| (i % 16 * 17) as u8).collect();
+    let file = greyscale_png(&data, 16, 16);
+    let mut state = StateManager::from_heightmap_png(&file, 15., -5., 100, 100, 4., 0, -10, &BiomeThresholds::new())
+        .unwrap();
+    // image columns go along x, from black at -5 to white at 10
+    for x in 0..16 {
+        assert_eq!(state.column_height(x, 3), Some(x - 5));
+    }
+    state.draw();
+    assert!(state.get_canvas().0.chunks(4).any(|p| p[3] > 0));
+    assert!(StateManager::from_heightmap_png(b"nope", 1., 0., 10, 10, 4., 0, 0, &BiomeThresholds::new()).is_err());
+    // heights past what a world can hold, or from a scale or offset that isn't a number, are errors
+    let (file, biomes) = (greyscale_png(&[0, 255, 0, 255], 2, 2), BiomeThresholds::new());
+    for (vertical_scale, offset) in [(5000., 0.), (1e9, 0.), (1., -3e9), (f32::NAN, 0.), (1., f32::INFINITY)] {
+        assert!(StateManager::from_heightmap_png(&file, vertical_scale, offset, 10, 10, 4., 0, 0, &biomes).is_err());
+    }
+    assert!(StateManager::from_heightmap_png(&file, 1000., 0., 10, 10, 4., 0, 0, &biomes).is_ok());
+}
+
+#[test]
+fn worlds_export_their_ground() {
+    // the ramp from above, with water up to z = 0 over its low end
+    let data: Vec<u8> = (0..16 * 16).map(|i| (i % 16 * 17) as u8).collect();
+    let biomes = BiomeThresholds::new();
+    let mut state = StateManager::from_heightmap_png(&greyscale_png(&data, 16, 16), 15., -5., 100, 100, 4., 0, 0, &biomes)
+        .unwrap();
+    let water = state.material_id("water").unwrap();
+    assert_eq!(state.get_block(0, 3, 0), Some(water));
+    // edits are part of the export
+    state.place_block(10, 3, 6, state.material_id("stone").unwrap());
+    let file = state.get_heightmap_png(15., -5.).unwrap();
+    let imported = StateManager::from_heightmap_png(&file, 15., -5., 100, 100, 4., 0, 0, &biomes).unwrap();
+    for x in 0..16 {
+        for y in 0..16 {
+            let (a, b) = (state.column_height(x, y).unwrap(), imported.column_height(x, y).unwrap());
+            assert!((a - b).abs() <= 1);
+        }
+    }
+    // the ground under the water is exported, not the water's surface
+    assert_eq!(imported.get_block(0, 3, -1), Some(water));
+    assert!((imported.column_height(10, 3).unwrap() - 6).abs() <= 1);
+    let shaded = state.get_hillshade_png(0.).unwrap();
+    assert_eq!(shaded[16..24], [0, 0, 0, 16, 0, 0, 0, 16]);
+    // infinite worlds have no edges to export
+    let infinite = StateManager::new_infinite(vec![10], vec![4.], 0, 16, 100, 100, 4., -10, &biomes);
+    assert_eq!(infinite.world_rows(), 0);
+    assert!(infinite.get_heightmap_png(16., -8.).is_err());
+    assert!(infinite.get_hillshade_png(0.).is_err());
+}
+
+#[test]
+fn noise_exports_as_images() {
+    let (periods, amplitudes) = (vec![10, 4], vec![6., 2.]);
+    let biomes = BiomeThresholds::new();
+    let no_erosion = ErosionParams { iterations: 0, thermal_iterations: 0, ..ErosionParams::new() };
+    let generated = StateManager::new(20, 20, periods.clone(), amplitudes.clone(), 100, 100, 4., 3, -10, &biomes, &no_erosion);
+    // reading the export back with twice the summed amplitudes as scale gives the same world, give or take rounding
+    let file = heightmap_png(20, 20, periods.clone(), amplitudes.clone(), 3, None).unwrap();
+    let imported = StateManager::from_heightmap_png(&file, 16., -8., 100, 100, 4., 3, -10, &biomes).unwrap();
+    for x in 0..20 {
+        for y in 0..20 {
+            let (a, b) = (generated.column_height(x, y).unwrap(), imported.column_height(x, y).unwrap());
+            assert!((a - b).abs() <= 1);
+        }
+    }
+    // each octave can be exported on its own, as a heightmap or a hillshade
+    let octave = heightmap_png(20, 20, periods.clone(), amplitudes.clone(), 3, Some(1)).unwrap();
+    assert_ne!(octave, file);
+    let shaded = hillshade_png(20, 20, periods.clone(), amplitudes.clone(), 3, 0., Some(0)).unwrap();
+    assert_eq!(shaded[..8], [0x89, b'P', b'N', b'G', 0x0d, 0x0a, 0x1a, 0x0a]);
+    assert!(heightmap_png(20, 20, periods.clone(), amplitudes.clone(), 3, Some(2)).is_err());
+    assert!(hillshade_png(20, 20, periods.clone(), vec![1.], 3, 0., None).is_err());
+    // zero periods and worlds narrower than 2 blocks are errors rather than panics
+    assert!(heightmap_png(20, 20, vec![0], vec![1.], 0, None).is_err());
+    assert!(hillshade_png(20, 20, vec![10, 0], vec![1., 1.], 0, 0., None).is_err());
+    assert!(heightmap_png(0, 20, periods.clone(), amplitudes.clone(), 3, None).is_err());
+    assert!(hillshade_png(20, 1, periods, amplitudes, 3, 0., None).is_err());
+}
+
+#[test]
+fn worlds_from_elevation_grids() {
+    // a 100m high slope along x, 50m cells, with a missing patch in the middle
+    let mut grid = String::from("ncols 8\nnrows 8\nxllcorner 0\nyllcorner 0\ncellsize 50\nNODATA_value -1\n");
+    for i in 0..8 {
+        let row: Vec<_> = (0..8)
+            .map(|j| if (3..5).contains(&i) && (3..5).contains(&j) { -1 } else { j * 100 })
+            .map(|e| e.to_string())
+            .collect();
+        grid += &(row.join(" ") + "\n");
+    }
+    let mut params = ElevationParams::new();
+    params.exaggeration = 2.;
+    let biomes = BiomeThresholds::new();
+    let state = StateManager::from_ascii_grid(&grid, &params, 100, 100, 4., 0, -10, &biomes).unwrap();
+    assert_eq!((state.world_rows(), state.world_cols()), (8, 8));
+    // each 100m step is two cells' width, doubled
+    assert_eq!(state.column_height(2, 0), Some(8));
+    assert_eq!(state.column_height(3, 3), None);
+    // missing cells can be water instead, and the grid resampled
+    params.no_data = NoData::Water;
+    params.rows = 16;
+    params.cols = 16;
+    let state = StateManager::from_ascii_grid(&grid, &params, 100, 100, 4., 0, 5, &biomes).unwrap();
+    assert_eq!((state.world_rows(), state.world_cols()), (16, 16));
+    let water = state.material_id("water").unwrap();
+    assert_eq!(state.get_block(7, 7, 5), Some(water));
+    // cells are half as wide, so heights take twice as many blocks: 175m, doubled, is 14 blocks
+    assert_eq!(state.column_height(4, 0), Some(14));
+    assert!(StateManager::from_ascii_grid("ncols 2", &params, 100, 100, 4., 0, 5, &biomes).is_err());
+    // a cell width overrides the grid's cellsize; at half of it heights double again
+    params.rows = 0;
+    params.cols = 0;
+    params.cell_width = 25.;
+    let state = StateManager::from_ascii_grid(&grid, &params, 100, 100, 4., 0, -10, &biomes).unwrap();
+    assert_eq!(state.column_height(2, 0), Some(16));
+    // heights too tall for the world are an error rather than gigabytes of blocks
+    let degrees = grid.replace("cellsize 50", "cellsize 0.000833");
+    params.cell_width = 0.;
+    assert!(StateManager::from_ascii_grid(&degrees, &params, 100, 100, 4., 0, -10, &biomes).is_err());
+    params.cell_width = 92.6;
+    assert!(StateManager::from_ascii_grid(&degrees, &params, 100, 100, 4., 0, -10, &biomes).is_ok());
+}